@@ -0,0 +1,243 @@
+use bevy::prelude::*;
+use hashbrown::HashMap;
+
+use crate::{simulation::SimulationTime, state::GameState};
+
+/// How a clip continues once its last frame is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackMode {
+    Loop,
+    Once,
+}
+
+impl Default for PlaybackMode {
+    fn default() -> Self {
+        Self::Loop
+    }
+}
+
+/// A single frame of an animation clip
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    // The index of the frame in the texture atlas
+    pub index: usize,
+    // The time the frame is shown in seconds
+    pub duration: f32,
+    // The name of the event that is sent when the frame is reached
+    pub event: Option<String>,
+}
+
+/// A sequence of frames in a texture atlas
+#[derive(Debug, Default, Clone)]
+pub struct AnimationClip {
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlaybackMode,
+}
+
+impl AnimationClip {
+    /// Creates a clip from a range of atlas indices that all share the same duration
+    pub fn from_range(first: usize, last: usize, frame_duration: f32, mode: PlaybackMode) -> Self {
+        Self {
+            frames: (first..=last)
+                .map(|index| AnimationFrame {
+                    index,
+                    duration: frame_duration,
+                    event: None,
+                })
+                .collect(),
+            mode,
+        }
+    }
+
+    /// Sends an event with a name when the frame is reached
+    pub fn with_event(mut self, frame: usize, name: &str) -> Self {
+        if let Some(frame) = self.frames.get_mut(frame) {
+            frame.event = Some(name.into());
+        }
+        self
+    }
+}
+
+/// Plays animation clips on the entity's [`TextureAtlasSprite`]
+#[derive(Debug, Default, Clone, Component)]
+pub struct Animator {
+    // The clips the animator can play, by name
+    pub clips: HashMap<String, AnimationClip>,
+    // The clip that is currently playing
+    current: Option<String>,
+    // The clip to play once the current one-shot clip is finished
+    queued: Option<String>,
+    // The index of the current frame in the clip
+    frame: usize,
+    // The time spent on the current frame
+    elapsed: f32,
+    // Whether the frame still has to be applied to the sprite
+    dirty: bool,
+    // Whether the current one-shot clip is finished
+    finished: bool,
+}
+
+impl Animator {
+    /// Add a clip to the animator
+    pub fn with_clip(mut self, name: &str, clip: AnimationClip) -> Self {
+        self.clips.insert(name.into(), clip);
+        self
+    }
+
+    /// Immediately start playing a clip, unless it is already playing
+    pub fn play(&mut self, name: &str) {
+        self.queued = None;
+        if self.current.as_deref() == Some(name) {
+            return;
+        }
+        self.restart(name);
+    }
+
+    /// Play a clip from its first frame, even if it is already playing
+    pub fn restart(&mut self, name: &str) {
+        if !self.clips.contains_key(name) {
+            warn!("Animation clip not found: {}", name);
+            return;
+        }
+        self.current = Some(name.into());
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.dirty = true;
+        self.finished = false;
+    }
+
+    /// Play a clip once the current one-shot clip is finished
+    /// Plays the clip immediately if the current clip loops or is already finished
+    pub fn queue(&mut self, name: &str) {
+        let waiting = !self.finished
+            && self
+                .current_clip()
+                .map_or(false, |clip| clip.mode == PlaybackMode::Once);
+        if waiting {
+            self.queued = Some(name.into());
+        } else {
+            self.play(name);
+        }
+    }
+
+    /// The name of the clip that is currently playing
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Check if the current one-shot clip is finished
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Get the clip that is currently playing
+    fn current_clip(&self) -> Option<&AnimationClip> {
+        self.current.as_ref().and_then(|name| self.clips.get(name))
+    }
+}
+
+/// An event that is sent when a frame with an event is reached
+#[derive(Debug, Clone)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub clip: String,
+    pub name: String,
+}
+
+/// An event that is sent when a one-shot clip is finished
+#[derive(Debug, Clone)]
+pub struct AnimationFinishedEvent {
+    pub entity: Entity,
+    pub clip: String,
+}
+
+/// The plugin that plays sprite sheet animations
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AnimationEvent>();
+        app.add_event::<AnimationFinishedEvent>();
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(advance_animations.label("advance_animations")),
+        );
+    }
+}
+
+/// Advances the animators and applies their frames to the sprites
+fn advance_animations(
+    time: Res<SimulationTime>,
+    mut animation_events: EventWriter<AnimationEvent>,
+    mut finished_events: EventWriter<AnimationFinishedEvent>,
+    mut animators: Query<(Entity, &mut Animator, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut animator, mut sprite) in animators.iter_mut() {
+        let animator = &mut *animator;
+        let name = match &animator.current {
+            Some(name) => name.clone(),
+            None => continue,
+        };
+        let clip = match animator.clips.get(&name) {
+            Some(clip) if !clip.frames.is_empty() => clip,
+            _ => continue,
+        };
+
+        // Apply a freshly started clip
+        if animator.dirty {
+            animator.dirty = false;
+            sprite.index = clip.frames[0].index;
+            if let Some(event) = &clip.frames[0].event {
+                animation_events.send(AnimationEvent {
+                    entity,
+                    clip: name.clone(),
+                    name: event.clone(),
+                });
+            }
+        }
+        if animator.finished {
+            continue;
+        }
+
+        // Advance the frames
+        animator.elapsed += time.delta_seconds();
+        while animator.elapsed >= clip.frames[animator.frame].duration {
+            animator.elapsed -= clip.frames[animator.frame].duration;
+
+            if animator.frame + 1 < clip.frames.len() {
+                animator.frame += 1;
+            } else if clip.mode == PlaybackMode::Loop {
+                animator.frame = 0;
+            } else {
+                animator.finished = true;
+                finished_events.send(AnimationFinishedEvent {
+                    entity,
+                    clip: name.clone(),
+                });
+                break;
+            }
+
+            let frame = &clip.frames[animator.frame];
+            sprite.index = frame.index;
+            if let Some(event) = &frame.event {
+                animation_events.send(AnimationEvent {
+                    entity,
+                    clip: name.clone(),
+                    name: event.clone(),
+                });
+            }
+
+            // Guard against frames without a duration
+            if frame.duration <= 0.0 {
+                break;
+            }
+        }
+
+        // Start the queued clip once the one-shot clip is done
+        if animator.finished {
+            if let Some(queued) = animator.queued.take() {
+                animator.play(&queued);
+            }
+        }
+    }
+}
@@ -16,12 +16,15 @@ pub struct AssetLoadingState {
     pub textures_loaded: bool,
     pub sounds_loaded: bool,
     pub models_loaded: bool,
+    pub items_loaded: bool,
+    pub loot_loaded: bool,
+    pub recipes_loaded: bool,
 }
 
 impl AssetLoadingState {
     /// Check if the asset loading is completed
     pub fn complete(&self) -> bool {
-        self.fonts_loaded // && self.textures_loaded && self.sounds_loaded && self.models_loaded
+        self.fonts_loaded && self.items_loaded && self.loot_loaded && self.recipes_loaded // && self.textures_loaded && self.sounds_loaded && self.models_loaded
     }
 }
 
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+use crate::simulation::SimulationTime;
+
 /// The target for the camera to follow
 #[derive(Debug, Clone, Component)]
 pub struct CameraTarget(pub Option<Entity>);
@@ -23,19 +25,66 @@ pub struct GameCameraConfig {
     pub target_id: Option<Entity>,
 }
 
+/// The marker component for the sprite that covers the screen when fading
+#[derive(Debug, Default, Clone, Component)]
+pub struct FadeOverlay;
+
+/// Fades the screen to black and back
+#[derive(Debug, Clone)]
+pub struct ScreenFade {
+    // The current opacity of the overlay
+    pub alpha: f32,
+    // The opacity the overlay moves towards
+    pub target: f32,
+    // The change in opacity per second
+    pub speed: f32,
+}
+
+impl Default for ScreenFade {
+    fn default() -> Self {
+        Self {
+            alpha: 0.0,
+            target: 0.0,
+            speed: 4.0,
+        }
+    }
+}
+
+impl ScreenFade {
+    /// Start fading to black
+    pub fn fade_out(&mut self) {
+        self.target = 1.0;
+    }
+
+    /// Start fading back in
+    pub fn fade_in(&mut self) {
+        self.target = 0.0;
+    }
+
+    /// Check if the fade reached its target
+    pub fn is_done(&self) -> bool {
+        (self.alpha - self.target).abs() < f32::EPSILON
+    }
+}
+
 /// The plugin that handles the game camera
 pub struct GameCameraPlugin;
 
 impl Plugin for GameCameraPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenFade>();
         app.add_startup_system(setup);
         app.add_system(camera_scaling);
         app.add_system(update_position);
+        app.add_system(update_fade);
     }
 }
 
 /// Setup the camera plugin
 fn setup(mut commands: Commands) {
+    // Spawn the camera for the menus
+    commands.spawn_bundle(UiCameraBundle::default());
+
     // Spawn the camera
     commands
         .spawn_bundle(OrthographicCameraBundle::new_2d())
@@ -46,7 +95,21 @@ fn setup(mut commands: Commands) {
         })
         .insert(CameraTarget(None))
         .insert(CameraSpeed(2.0))
-        .insert(CameraOffset(Vec2::new(0.0, 24.0)));
+        .insert(CameraOffset(Vec2::new(0.0, 24.0)))
+        .with_children(|parent| {
+            // The overlay that follows the camera, just in front of it
+            parent
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(0.0, 0.0, 0.0, 0.0),
+                        custom_size: Vec2::new(4096.0, 4096.0).into(),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, -1.0),
+                    ..Default::default()
+                })
+                .insert(FadeOverlay);
+        });
 }
 
 /// Handles the camera scaling
@@ -96,3 +159,21 @@ fn update_position(
         }
     }
 }
+
+/// Moves the fade towards its target and applies it to the overlay
+fn update_fade(
+    time: Res<SimulationTime>,
+    mut fade: ResMut<ScreenFade>,
+    mut overlays: Query<&mut Sprite, With<FadeOverlay>>,
+) {
+    let step = fade.speed * time.delta_seconds();
+    fade.alpha = if fade.alpha < fade.target {
+        (fade.alpha + step).min(fade.target)
+    } else {
+        (fade.alpha - step).max(fade.target)
+    };
+
+    overlays.for_each_mut(|mut sprite| {
+        sprite.color.set_a(fade.alpha);
+    });
+}
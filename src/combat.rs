@@ -0,0 +1,367 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    dimension::{ActiveView, Dimensional},
+    health::{DamageEvent, Health, Invulnerable},
+    items::{
+        equipment::{Equipment, StatModifiers},
+        ItemCategory, ItemRegistry,
+    },
+    levels::LevelObject,
+    physics::{component::Velocity, overlaps},
+    player::component::{Player, PlayerState},
+    projectile::{GroundContact, SpawnProjectileEvent},
+    simulation::SimulationTime,
+    state::GameState,
+};
+
+/// The direction an entity is facing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub enum Facing {
+    Left,
+    Right,
+}
+
+impl Default for Facing {
+    fn default() -> Self {
+        Self::Right
+    }
+}
+
+impl Facing {
+    /// The sign of the x axis in the facing direction
+    pub fn sign(self) -> f32 {
+        match self {
+            Self::Left => -1.0,
+            Self::Right => 1.0,
+        }
+    }
+}
+
+/// A melee attack as it is written in the definition of a weapon
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MeleeAttack {
+    // The frames before the hitbox comes out
+    #[serde(default)]
+    pub windup: u32,
+    // The frames the hitbox stays out
+    pub active: u32,
+    // The width and height of the hitbox
+    pub size: (f32, f32),
+    // The distance between the attacker and the center of the hitbox, in the facing direction
+    #[serde(default)]
+    pub reach: f32,
+    // The damage dealt to each target, before the attacker's damage bonus
+    pub damage: u32,
+    // The strength of the knockback away from the attacker
+    #[serde(default)]
+    pub knockback: f32,
+    // The time in seconds before the attacker can attack again
+    pub cooldown: f32,
+}
+
+/// A ranged attack as it is written in the definition of a weapon, it fires a projectile
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RangedAttack {
+    // The path of the projectile sprite, relative to the assets folder
+    pub projectile: String,
+    // The width and height of the projectile
+    pub size: (f32, f32),
+    // The horizontal speed in the facing direction
+    pub speed: f32,
+    // The upward speed the projectile is fired with
+    #[serde(default)]
+    pub lift: f32,
+    #[serde(default)]
+    pub gravity_scale: f32,
+    // The damage dealt to each target, before the attacker's damage bonus
+    pub damage: u32,
+    #[serde(default)]
+    pub knockback: f32,
+    // The time in seconds before the projectile is gone
+    pub lifetime: f32,
+    // The number of targets the projectile passes through
+    #[serde(default)]
+    pub pierce: u32,
+    // The collider tags of the targets it hits
+    pub hits: Vec<String>,
+    #[serde(default)]
+    pub ground: GroundContact,
+    // The time in seconds before the attacker can attack again
+    pub cooldown: f32,
+}
+
+/// A box that the hitboxes can damage, centered on the entity
+#[derive(Debug, Clone, Component)]
+pub struct Hurtbox {
+    pub half_extents: Vec2,
+}
+
+/// A short-lived box that damages the hurtboxes it overlaps
+#[derive(Debug, Clone, Component)]
+pub struct Hitbox {
+    // The entity that attacked
+    pub owner: Entity,
+    // The offset from the owner, already turned in the facing direction
+    pub offset: Vec2,
+    pub half_extents: Vec2,
+    pub damage: u32,
+    pub knockback: f32,
+    // The sign of the x axis the attack points in
+    pub direction: f32,
+    // The frames left before the hitbox comes out
+    pub windup: u32,
+    // The frames left while the hitbox is out
+    pub active: u32,
+    // The entities this swing already hit, they aren't hit again
+    pub hit: Vec<Entity>,
+}
+
+/// Stops an entity from attacking until the time runs out
+#[derive(Debug, Clone, Component)]
+pub struct AttackCooldown {
+    // The time left in seconds
+    pub remaining: f32,
+}
+
+/// An event that makes an entity swing a melee attack
+#[derive(Debug, Clone)]
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub attack: MeleeAttack,
+}
+
+/// The plugin that handles the melee attacks
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AttackEvent>();
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(update_facing.label("update_facing").after("handle_movement"))
+                .with_system(attack_input.label("attack_input").after("update_input"))
+                .with_system(
+                    spawn_hitboxes
+                        .label("spawn_hitboxes")
+                        .after("attack_input")
+                        .after("update_facing"),
+                )
+                .with_system(
+                    handle_hitbox_hits
+                        .label("handle_hitbox_hits")
+                        .after("spawn_hitboxes")
+                        .before("apply_damage"),
+                )
+                .with_system(tick_attack_cooldowns),
+        );
+    }
+}
+
+/// Turn the entities in the direction they are moving, attackers keep facing their target until
+/// they can attack again
+fn update_facing(mut entities: Query<(&mut Facing, &Velocity), Without<AttackCooldown>>) {
+    entities.for_each_mut(|(mut facing, velocity)| {
+        if velocity.x < 0.0 && *facing != Facing::Left {
+            *facing = Facing::Left;
+        } else if velocity.x > 0.0 && *facing != Facing::Right {
+            *facing = Facing::Right;
+        }
+    });
+}
+
+/// Swing or fire the equipped weapon when a player presses the attack button
+fn attack_input(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    item_registry: Res<ItemRegistry>,
+    mut players: Query<
+        (
+            Entity,
+            &mut PlayerState,
+            &Equipment,
+            Option<&StatModifiers>,
+            &Transform,
+            Option<&Facing>,
+            Option<&AttackCooldown>,
+        ),
+        With<Player>,
+    >,
+    mut attack_events: EventWriter<AttackEvent>,
+    mut projectile_events: EventWriter<SpawnProjectileEvent>,
+) {
+    players.for_each_mut(
+        |(entity, mut state, equipment, modifiers, transform, facing, cooldown)| {
+            let attack_pressed = state.input.is_attacking && !state.attack_held;
+            state.attack_held = state.input.is_attacking;
+            if !attack_pressed {
+                return;
+            }
+
+            // Only weapons with attack data can be swung or fired
+            let weapon = equipment
+                .weapon
+                .as_ref()
+                .and_then(|id| item_registry.get(id))
+                .filter(|item| item.category == ItemCategory::Weapon);
+            let bonus = modifiers.map_or(0, |modifiers| modifiers.damage);
+            if let Some(mut attack) = weapon.and_then(|item| item.attack.clone()) {
+                attack.damage += bonus;
+                attack_events.send(AttackEvent {
+                    attacker: entity,
+                    attack,
+                });
+            } else if let Some(ranged) = weapon.and_then(|item| item.ranged.as_ref()) {
+                if cooldown.is_some() {
+                    return;
+                }
+                let direction = facing.copied().unwrap_or_default().sign();
+                projectile_events.send(SpawnProjectileEvent {
+                    texture: asset_server.load(ranged.projectile.as_str()),
+                    position: transform.translation.truncate(),
+                    velocity: Vec2::new(ranged.speed * direction, ranged.lift),
+                    gravity_scale: ranged.gravity_scale,
+                    half_extents: Vec2::new(ranged.size.0, ranged.size.1) / 2.0,
+                    owner: Some(entity),
+                    damage: ranged.damage + bonus,
+                    knockback: ranged.knockback,
+                    lifetime: ranged.lifetime,
+                    pierce: ranged.pierce,
+                    hits: ranged.hits.clone(),
+                    ground: ranged.ground,
+                });
+                commands.entity(entity).insert(AttackCooldown {
+                    remaining: ranged.cooldown,
+                });
+            }
+        },
+    );
+}
+
+/// Spawn a hitbox in front of the attackers that aren't cooling down
+fn spawn_hitboxes(
+    mut commands: Commands,
+    attackers: Query<(&Transform, Option<&Facing>), Without<AttackCooldown>>,
+    mut attack_events: EventReader<AttackEvent>,
+) {
+    let mut attacked = vec![];
+    for event in attack_events.iter() {
+        // An entity only starts one attack at a time
+        if attacked.contains(&event.attacker) {
+            continue;
+        }
+        let (transform, facing) = match attackers.get(event.attacker) {
+            Ok(attacker) => attacker,
+            Err(_) => continue,
+        };
+        attacked.push(event.attacker);
+
+        let attack = &event.attack;
+        let direction = facing.copied().unwrap_or_default().sign();
+        let offset = Vec2::new(attack.reach * direction, 0.0);
+        commands
+            .spawn()
+            .insert(Transform::from_translation(
+                transform.translation + offset.extend(0.0),
+            ))
+            .insert(Hitbox {
+                owner: event.attacker,
+                offset,
+                half_extents: Vec2::new(attack.size.0, attack.size.1) / 2.0,
+                damage: attack.damage,
+                knockback: attack.knockback,
+                direction,
+                windup: attack.windup,
+                active: attack.active,
+                hit: vec![],
+            })
+            .insert(LevelObject);
+        commands.entity(event.attacker).insert(AttackCooldown {
+            remaining: attack.cooldown,
+        });
+    }
+}
+
+/// Move the hitboxes with their owners, damage the hurtboxes they overlap and despawn them once
+/// they are done
+fn handle_hitbox_hits(
+    mut commands: Commands,
+    active_view: Res<ActiveView>,
+    mut hitboxes: Query<(Entity, &mut Hitbox, &mut Transform)>,
+    owners: Query<(&Transform, Option<&Player>), Without<Hitbox>>,
+    hurtboxes: Query<
+        (Entity, &Hurtbox, &Transform, Option<&Player>, Option<&Dimensional>),
+        (With<Health>, Without<Invulnerable>, Without<Hitbox>),
+    >,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    hitboxes.for_each_mut(|(entity, mut hitbox, mut transform)| {
+        let (owner_transform, owner_player) = match owners.get(hitbox.owner) {
+            Ok(owner) => owner,
+            // The swing ends with its owner
+            Err(_) => {
+                commands.entity(entity).despawn();
+                return;
+            }
+        };
+        transform.translation = owner_transform.translation + hitbox.offset.extend(0.0);
+
+        if hitbox.windup > 0 {
+            hitbox.windup -= 1;
+            return;
+        }
+        if hitbox.active == 0 {
+            commands.entity(entity).despawn();
+            return;
+        }
+        hitbox.active -= 1;
+
+        let position = transform.translation.truncate();
+        for (target, hurtbox, target_transform, target_player, dimensional) in hurtboxes.iter() {
+            // Players don't hit themselves or each other
+            if target == hitbox.owner || (owner_player.is_some() && target_player.is_some()) {
+                continue;
+            }
+            // Only the hurtboxes in the active view can be hit
+            if !dimensional.map_or(true, |dimensional| dimensional.exists_in(active_view.view)) {
+                continue;
+            }
+            if hitbox.hit.contains(&target) {
+                continue;
+            }
+            if !overlaps(
+                position,
+                hitbox.half_extents,
+                target_transform.translation.truncate(),
+                hurtbox.half_extents,
+            ) {
+                continue;
+            }
+
+            // Knock the target away from the attacker, and a little upwards
+            let knockback = Vec2::new(hitbox.direction, 1.0).normalize() * hitbox.knockback;
+            damage_events.send(DamageEvent {
+                target,
+                source: Some(hitbox.owner),
+                amount: hitbox.damage,
+                knockback,
+            });
+            hitbox.hit.push(target);
+        }
+    });
+}
+
+/// Count down the attack cooldowns
+fn tick_attack_cooldowns(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut cooldowns: Query<(Entity, &mut AttackCooldown)>,
+) {
+    cooldowns.for_each_mut(|(entity, mut cooldown)| {
+        cooldown.remaining -= time.delta_seconds();
+        if cooldown.remaining <= 0.0 {
+            commands.entity(entity).remove::<AttackCooldown>();
+        }
+    });
+}
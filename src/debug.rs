@@ -1,7 +1,10 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext};
 
-use crate::{items::inventory::Inventory, player::component::Player};
+use crate::{
+    items::{inventory::Inventory, shop::Wallet, ItemRegistry},
+    player::component::Player,
+};
 
 /// The state of the deug menu
 #[derive(Debug, Default, Clone)]
@@ -44,7 +47,8 @@ fn toggle_debug_menu(input: Res<Input<KeyCode>>, mut state: ResMut<DebugMenuStat
 /// Draw the debug menu using egui
 fn draw_debug_menu(
     state: Res<DebugMenuState>,
-    inventories: Query<(Entity, &Inventory), With<Player>>,
+    item_registry: Res<ItemRegistry>,
+    inventories: Query<(Entity, &Inventory, Option<&Wallet>), With<Player>>,
     mut egui: ResMut<EguiContext>,
 ) {
     // If the debug menu is not supposed to be shown, return
@@ -56,10 +60,16 @@ fn draw_debug_menu(
     egui::Window::new("Inventories")
         .show(egui.ctx_mut(), |ui| {
             // Draw the invwentory for each player
-            inventories.for_each(|(entity, inventory)| {
+            inventories.for_each(|(entity, inventory, wallet)| {
                 ui.collapsing(format!("Player: {}", entity.id()), |ui| {
-                    for item in inventory.items.iter() {
-                        ui.label(format!("{}", item.name));
+                    if let Some(wallet) = wallet {
+                        ui.label(format!("Coins: {}", wallet.coins));
+                    }
+                    for (_, stack) in inventory.stacks() {
+                        let name = item_registry
+                            .get(&stack.id)
+                            .map_or(stack.id.as_str(), |item| item.name.as_str());
+                        ui.label(format!("{} x{}", name, stack.count));
                     }
                 });
             });
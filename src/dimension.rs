@@ -0,0 +1,335 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::GameCameraConfig,
+    levels::{RespawnPhase, RespawnSequence},
+    physics::{component::PhysicsBody, PhysicsConfig},
+    player::component::PlayerState,
+    simulation::SimulationTime,
+    state::GameState,
+};
+
+/// The view the level is seen from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    // Looking at the level from the front, the horizontal axis is the width
+    Front,
+    // Looking at the level from the side, the horizontal axis is the depth
+    Side,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self::Front
+    }
+}
+
+impl View {
+    /// Get the view the flip leads to
+    pub fn flipped(self) -> Self {
+        match self {
+            Self::Front => Self::Side,
+            Self::Side => Self::Front,
+        }
+    }
+}
+
+/// The view the level is currently seen and collided from
+#[derive(Debug, Default, Clone)]
+pub struct ActiveView {
+    pub view: View,
+}
+
+/// The position of a level object in each view
+/// An object without a position in a view doesn't exist in that view
+/// Objects without this component exist in both views at the same position
+#[derive(Debug, Clone, Component)]
+pub struct Dimensional {
+    pub front: Option<Vec2>,
+    pub side: Option<Vec2>,
+}
+
+impl Dimensional {
+    /// Creates an object that only exists in the front view
+    pub fn front(position: Vec2) -> Self {
+        Self {
+            front: Some(position),
+            side: None,
+        }
+    }
+
+    /// Creates an object that only exists in the side view
+    pub fn side(position: Vec2) -> Self {
+        Self {
+            front: None,
+            side: Some(position),
+        }
+    }
+
+    /// Creates an object that exists in both views
+    pub fn both(front: Vec2, side: Vec2) -> Self {
+        Self {
+            front: Some(front),
+            side: Some(side),
+        }
+    }
+
+    /// Get the position of the object in a view
+    pub fn position(&self, view: View) -> Option<Vec2> {
+        match view {
+            View::Front => self.front,
+            View::Side => self.side,
+        }
+    }
+
+    /// Check if the object exists in a view
+    pub fn exists_in(&self, view: View) -> bool {
+        self.position(view).is_some()
+    }
+}
+
+/// An event that flips the view
+pub struct FlipEvent;
+
+/// The animation of the camera turning to the other view
+#[derive(Debug, Clone)]
+pub struct FlipTransition {
+    // The time spent in the transition, if one is running
+    pub elapsed: Option<f32>,
+    // The time the whole transition takes
+    pub duration: f32,
+    // Whether the level objects were already moved to the new view
+    pub swapped: bool,
+}
+
+impl Default for FlipTransition {
+    fn default() -> Self {
+        Self {
+            elapsed: None,
+            duration: 0.5,
+            swapped: false,
+        }
+    }
+}
+
+/// The plugin that handles flipping between the front and side view
+pub struct DimensionPlugin;
+
+impl Plugin for DimensionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveView>();
+        app.init_resource::<FlipTransition>();
+        app.add_event::<FlipEvent>();
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(flip_input.label("flip_input").after("update_input"))
+                .with_system(start_flip.label("start_flip").after("flip_input"))
+                .with_system(run_flip.after("start_flip").before("apply_gravity")),
+        );
+        app.add_system_set(
+            SystemSet::on_exit(GameState::Gameplay).with_system(finish_flip),
+        );
+        app.add_system(reset_view_on_respawn.before("run_respawn_sequence"));
+    }
+}
+
+/// Flip when a player presses the flip button
+fn flip_input(mut players: Query<&mut PlayerState>, mut flip_events: EventWriter<FlipEvent>) {
+    let mut pressed = false;
+    players.for_each_mut(|mut state| {
+        pressed |= state.input.is_flipping && !state.flip_held;
+        state.flip_held = state.input.is_flipping;
+    });
+    if pressed {
+        flip_events.send(FlipEvent);
+    }
+}
+
+/// Start the flip transition
+fn start_flip(
+    mut transition: ResMut<FlipTransition>,
+    mut physics_conf: ResMut<PhysicsConfig>,
+    mut flip_events: EventReader<FlipEvent>,
+) {
+    if flip_events.iter().count() == 0 || transition.elapsed.is_some() {
+        return;
+    }
+    transition.elapsed = Some(0.0);
+    transition.swapped = false;
+    // The world holds still while the camera turns
+    physics_conf.enabled = false;
+}
+
+/// Animate the camera and move the level objects to the new view halfway through
+fn run_flip(
+    time: Res<SimulationTime>,
+    mut transition: ResMut<FlipTransition>,
+    mut active_view: ResMut<ActiveView>,
+    mut physics_conf: ResMut<PhysicsConfig>,
+    mut cameras: Query<&mut Transform, With<GameCameraConfig>>,
+    mut objects: Query<
+        (
+            &mut Dimensional,
+            &mut Transform,
+            Option<&PhysicsBody>,
+            Option<&mut Visibility>,
+        ),
+        Without<GameCameraConfig>,
+    >,
+) {
+    let elapsed = match transition.elapsed {
+        Some(elapsed) => elapsed + time.delta_seconds(),
+        None => return,
+    };
+    let progress = (elapsed / transition.duration).min(1.0);
+    transition.elapsed = Some(elapsed);
+
+    // Squash the view to a line and open it up again, like a page turning
+    cameras.for_each_mut(|mut transform| {
+        transform.scale.x = (1.0 - progress * 2.0).abs().max(0.01);
+    });
+
+    // Swap the views when the camera is edge on
+    if progress >= 0.5 && !transition.swapped {
+        transition.swapped = true;
+        let from = active_view.view;
+        active_view.view = from.flipped();
+        objects.for_each_mut(|(mut dimensional, mut transform, body, mut visibility)| {
+            move_to_view(
+                from,
+                &mut dimensional,
+                &mut transform,
+                body,
+                visibility.as_deref_mut(),
+            );
+        });
+    }
+
+    if progress >= 1.0 {
+        transition.elapsed = None;
+        physics_conf.enabled = true;
+        cameras.for_each_mut(|mut transform| {
+            transform.scale.x = 1.0;
+        });
+    }
+}
+
+/// Move a level object from a view to the other one, hiding it if it doesn't exist there
+fn move_to_view(
+    from: View,
+    dimensional: &mut Dimensional,
+    transform: &mut Transform,
+    body: Option<&PhysicsBody>,
+    visibility: Option<&mut Visibility>,
+) {
+    let to = from.flipped();
+    let current = transform.translation.truncate();
+
+    // Moving bodies keep their height and the axis that is hidden in the current view
+    if body == Some(&PhysicsBody::Dynamic) {
+        let hidden = dimensional.position(to).unwrap_or(current);
+        match from {
+            View::Front => {
+                dimensional.front = Some(current);
+                dimensional.side = Some(Vec2::new(hidden.x, current.y));
+            }
+            View::Side => {
+                dimensional.side = Some(current);
+                dimensional.front = Some(Vec2::new(hidden.x, current.y));
+            }
+        }
+    }
+
+    if let Some(position) = dimensional.position(to) {
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+    if let Some(visibility) = visibility {
+        visibility.is_visible = dimensional.exists_in(to);
+    }
+}
+
+/// Finish a running flip when gameplay is left, so the view, the camera and the physics aren't
+/// left halfway
+fn finish_flip(
+    mut transition: ResMut<FlipTransition>,
+    mut active_view: ResMut<ActiveView>,
+    mut physics_conf: ResMut<PhysicsConfig>,
+    mut cameras: Query<&mut Transform, With<GameCameraConfig>>,
+    mut objects: Query<
+        (
+            &mut Dimensional,
+            &mut Transform,
+            Option<&PhysicsBody>,
+            Option<&mut Visibility>,
+        ),
+        Without<GameCameraConfig>,
+    >,
+) {
+    if transition.elapsed.is_none() {
+        return;
+    }
+    if !transition.swapped {
+        transition.swapped = true;
+        let from = active_view.view;
+        active_view.view = from.flipped();
+        objects.for_each_mut(|(mut dimensional, mut transform, body, mut visibility)| {
+            move_to_view(
+                from,
+                &mut dimensional,
+                &mut transform,
+                body,
+                visibility.as_deref_mut(),
+            );
+        });
+    }
+    transition.elapsed = None;
+    physics_conf.enabled = true;
+    cameras.for_each_mut(|mut transform| {
+        transform.scale.x = 1.0;
+    });
+}
+
+/// Go back to the front view when the players respawn, before they are moved to their spawn
+/// points and the room is reset
+fn reset_view_on_respawn(
+    sequence: Res<RespawnSequence>,
+    mut transition: ResMut<FlipTransition>,
+    mut active_view: ResMut<ActiveView>,
+    mut physics_conf: ResMut<PhysicsConfig>,
+    mut cameras: Query<&mut Transform, With<GameCameraConfig>>,
+    mut objects: Query<
+        (
+            &mut Dimensional,
+            &mut Transform,
+            Option<&PhysicsBody>,
+            Option<&mut Visibility>,
+        ),
+        Without<GameCameraConfig>,
+    >,
+) {
+    if sequence.phase != RespawnPhase::Resetting {
+        return;
+    }
+    // A running flip is dropped, its view is only kept if it was already swapped
+    if transition.elapsed.is_some() {
+        transition.elapsed = None;
+        physics_conf.enabled = true;
+        cameras.for_each_mut(|mut transform| {
+            transform.scale.x = 1.0;
+        });
+    }
+    if active_view.view == View::Front {
+        return;
+    }
+    active_view.view = View::Front;
+    objects.for_each_mut(|(mut dimensional, mut transform, body, mut visibility)| {
+        move_to_view(
+            View::Side,
+            &mut dimensional,
+            &mut transform,
+            body,
+            visibility.as_deref_mut(),
+        );
+    });
+}
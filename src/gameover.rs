@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    levels::{DestroyLevelsEvent, SpawnLevelEvent},
+    state::GameState,
+};
+
+/// The plugin for handling the game over state
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::GameOver).with_system(draw_game_over_menu),
+        );
+    }
+}
+
+/// Draws the game over menu
+fn draw_game_over_menu(
+    mut gamestate: ResMut<State<GameState>>,
+    mut egui: ResMut<EguiContext>,
+    mut destroy_level_events: EventWriter<DestroyLevelsEvent>,
+    mut spawn_level_events: EventWriter<SpawnLevelEvent>,
+) {
+    egui::Window::new("Game Over")
+        .title_bar(true)
+        .anchor(egui::Align2::CENTER_CENTER, &[0.0, 0.0])
+        .fixed_pos(&[0.0, 0.0])
+        .fixed_size(&[300.0, 200.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(egui.ctx_mut(), |ui| {
+            ui.vertical_centered_justified(|ui| {
+                // The retry button, reloads the level with the same players
+                if ui.button("Retry").clicked() {
+                    destroy_level_events.send(DestroyLevelsEvent);
+                    spawn_level_events.send(SpawnLevelEvent("".into()));
+                    if *gamestate.current() != GameState::Gameplay {
+                        gamestate.set(GameState::Gameplay).unwrap();
+                    }
+                }
+                // The exit buttom
+                if ui.button("Exit").clicked() {
+                    if *gamestate.current() != GameState::MainMenu {
+                        gamestate.set(GameState::MainMenu).unwrap();
+                    }
+                }
+            });
+        });
+}
@@ -1,6 +1,10 @@
 use bevy::prelude::*;
 
-use crate::state::GameState;
+use crate::{
+    items::menu::InventoryMenuState,
+    player::component::{Player, PlayerState},
+    state::GameState,
+};
 
 /// The plugin that manages the gameplay state
 pub struct GameplayPlugin;
@@ -10,7 +14,7 @@ impl Plugin for GameplayPlugin {
         app.add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(setup));
         app.add_system_set(SystemSet::on_update(GameState::Gameplay)
             .with_system(pause_game)
-            .with_system(open_player_menu)
+            .with_system(open_player_menu.after("update_input"))
         );
     }
 }
@@ -22,18 +26,33 @@ fn setup() {
 
 /// Pause the game on escape
 fn pause_game(keyboard_input: Res<Input<KeyCode>>, mut state: ResMut<State<GameState>>) {
-    if keyboard_input.just_pressed(KeyCode::Escape) {
-        if *state.current() != GameState::Paused {
-            state.set(GameState::Paused).unwrap();
-        }
+    // The pause is skipped when a menu is already opening this frame
+    if keyboard_input.just_pressed(KeyCode::Escape) && *state.current() != GameState::Paused {
+        state.set(GameState::Paused).ok();
     }
 }
 
-/// Opens the player menu on Q pressed
-fn open_player_menu(keyboard_input: Res<Input<KeyCode>>, mut state: ResMut<State<GameState>>) {
-    if keyboard_input.just_pressed(KeyCode::Q) {
-        if *state.current() != GameState::Menu {
-            state.set(GameState::Menu).unwrap();
+/// Opens the player menu for the player that presses the menu button
+/// The button is part of the player input, so the replays open the menu at the same tick
+fn open_player_menu(
+    mut players: Query<(Entity, &mut PlayerState), With<Player>>,
+    mut menu: ResMut<InventoryMenuState>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let mut opener = None;
+    players.for_each_mut(|(entity, mut player)| {
+        if player.input.is_opening_menu && !player.menu_held && opener.is_none() {
+            opener = Some(entity);
         }
+        player.menu_held = player.input.is_opening_menu;
+    });
+    let opener = match opener {
+        Some(opener) => opener,
+        None => return,
+    };
+    // Another state change like a game over can already be queued this frame
+    if *state.current() != GameState::Menu && state.set(GameState::Menu).is_err() {
+        return;
     }
-}
\ No newline at end of file
+    menu.owner = Some(opener);
+}
@@ -0,0 +1,232 @@
+use bevy::prelude::*;
+
+use crate::{
+    levels::RespawnEvent,
+    physics::component::{CollidingEntities, Velocity},
+    player::component::Player,
+    simulation::SimulationTime,
+    state::GameState,
+};
+
+/// The health of an entity
+#[derive(Debug, Clone, Component)]
+pub struct Health {
+    // The current amount of health
+    pub current: u32,
+    // The maximum amount of health
+    pub max: u32,
+    // The time in seconds the entity can't be damaged after being hit
+    pub invulnerability: f32,
+}
+
+impl Health {
+    /// Creates a new [`Health`] at full health
+    pub fn new(max: u32, invulnerability: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            invulnerability,
+        }
+    }
+
+    /// Check if the entity is dead
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+
+    /// Heal the entity, returns the amount that was healed
+    pub fn heal(&mut self, amount: u32) -> u32 {
+        let healed = amount.min(self.max - self.current);
+        self.current += healed;
+        healed
+    }
+}
+
+/// Marks an entity that can't be damaged right now
+#[derive(Debug, Clone, Component)]
+pub struct Invulnerable {
+    // The time left in seconds
+    pub remaining: f32,
+}
+
+/// Marks a collider that damages the entities touching it
+#[derive(Debug, Clone, Component)]
+pub struct Hazard {
+    // The damage dealt on contact
+    pub damage: u32,
+    // The strength of the knockback away from the hazard
+    pub knockback: f32,
+}
+
+/// An event that damages an entity
+#[derive(Debug, Clone)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub amount: u32,
+    pub knockback: Vec2,
+}
+
+/// An event that is sent when an entity runs out of health
+#[derive(Debug, Clone)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub source: Option<Entity>,
+}
+
+/// What happens when a player dies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeathRule {
+    GameOver,
+    Respawn,
+}
+
+/// The rules for dying
+#[derive(Debug, Clone)]
+pub struct DeathRules {
+    pub player: DeathRule,
+}
+
+impl Default for DeathRules {
+    fn default() -> Self {
+        Self {
+            player: DeathRule::Respawn,
+        }
+    }
+}
+
+/// The plugin that handles health, damage and death
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DeathRules>();
+        app.add_event::<DamageEvent>();
+        app.add_event::<DeathEvent>();
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(handle_hazards.label("handle_hazards"))
+                .with_system(
+                    apply_damage
+                        .label("apply_damage")
+                        .after("handle_hazards")
+                        .after("handle_movement")
+                        .before("apply_gravity"),
+                )
+                .with_system(handle_deaths.label("handle_deaths").after("apply_damage"))
+                .with_system(update_invulnerability),
+        );
+    }
+}
+
+/// Damage the entities that touch a hazard
+fn handle_hazards(
+    hazards: Query<(&Hazard, &Transform)>,
+    targets: Query<(Entity, &Transform, &CollidingEntities), (With<Health>, Without<Invulnerable>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    targets.for_each(|(entity, transform, colliding_entities)| {
+        for colliding_entity in colliding_entities.iter() {
+            if let Ok((hazard, hazard_transform)) = hazards.get(*colliding_entity) {
+                // Knock the target away from the hazard, and a little upwards
+                let away = (transform.translation - hazard_transform.translation).truncate();
+                let direction = (away.normalize_or_zero() + Vec2::Y).normalize_or_zero();
+
+                damage_events.send(DamageEvent {
+                    target: entity,
+                    source: Some(*colliding_entity),
+                    amount: hazard.damage,
+                    knockback: direction * hazard.knockback,
+                });
+                // Only get hurt by one hazard at a time
+                break;
+            }
+        }
+    });
+}
+
+/// Apply the damage events to the health of the targets
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut targets: Query<(&mut Health, Option<&mut Velocity>), Without<Invulnerable>>,
+) {
+    // Entities that got hit this frame, they can only be hit once
+    let mut hit = vec![];
+
+    for event in damage_events.iter() {
+        if hit.contains(&event.target) {
+            continue;
+        }
+        if let Ok((mut health, velocity)) = targets.get_mut(event.target) {
+            if health.is_dead() {
+                continue;
+            }
+            hit.push(event.target);
+
+            health.current = health.current.saturating_sub(event.amount);
+            if let Some(mut velocity) = velocity {
+                velocity.0 = event.knockback;
+            }
+
+            if health.is_dead() {
+                death_events.send(DeathEvent {
+                    entity: event.target,
+                    source: event.source,
+                });
+            } else if health.invulnerability > 0.0 {
+                commands.entity(event.target).insert(Invulnerable {
+                    remaining: health.invulnerability,
+                });
+            }
+        }
+    }
+}
+
+/// Decide what happens to the entities that died
+fn handle_deaths(
+    rules: Res<DeathRules>,
+    players: Query<(), With<Player>>,
+    mut commands: Commands,
+    mut gamestate: ResMut<State<GameState>>,
+    mut death_events: EventReader<DeathEvent>,
+    mut respawn_events: EventWriter<RespawnEvent>,
+) {
+    for event in death_events.iter() {
+        if players.get(event.entity).is_ok() {
+            match rules.player {
+                DeathRule::GameOver => {
+                    // The game over wins over a menu that is opened on the same frame
+                    if *gamestate.current() != GameState::GameOver {
+                        gamestate.overwrite_set(GameState::GameOver).unwrap();
+                    }
+                }
+                DeathRule::Respawn => respawn_events.send(RespawnEvent(event.entity)),
+            }
+        } else {
+            commands.entity(event.entity).despawn_recursive();
+        }
+    }
+}
+
+/// Count down the invulnerability and flash the sprite while it lasts
+fn update_invulnerability(
+    time: Res<SimulationTime>,
+    mut commands: Commands,
+    mut entities: Query<(Entity, &mut Invulnerable, Option<&mut Visibility>)>,
+) {
+    entities.for_each_mut(|(entity, mut invulnerable, visibility)| {
+        invulnerable.remaining -= time.delta_seconds();
+
+        if invulnerable.remaining <= 0.0 {
+            commands.entity(entity).remove::<Invulnerable>();
+            if let Some(mut visibility) = visibility {
+                visibility.is_visible = true;
+            }
+        } else if let Some(mut visibility) = visibility {
+            // Flash ten times per second
+            visibility.is_visible = (invulnerable.remaining * 10.0) as u32 % 2 == 0;
+        }
+    });
+}
@@ -0,0 +1,442 @@
+use bevy::prelude::*;
+
+use super::{
+    inventory::{transfer, Inventory, TransferError},
+    menu::{close_menu, MenuInput},
+    ItemId, ItemRegistry,
+};
+use crate::{
+    assets::Fonts,
+    levels::Lock,
+    physics::component::CollidingEntities,
+    player::component::{Player, PlayerState},
+    state::GameState,
+};
+
+/// Marks a chest, stash or bag with its own [`Inventory`] that the players can open
+#[derive(Debug, Clone, Component)]
+pub struct Container {
+    pub name: String,
+}
+
+/// An event that moves units of the item in an inventory slot into another inventory
+#[derive(Debug, Clone)]
+pub struct TransferItemEvent {
+    pub from: Entity,
+    pub slot: usize,
+    pub to: Entity,
+    pub count: u32,
+}
+
+/// An event that is sent when units were moved between two inventories
+#[derive(Debug, Clone)]
+pub struct ItemsTransferredEvent {
+    pub from: Entity,
+    pub to: Entity,
+    pub item: ItemId,
+    pub count: u32,
+}
+
+/// An event that is sent when a transfer didn't move anything
+#[derive(Debug, Clone)]
+pub struct TransferFailedEvent {
+    pub from: Entity,
+    pub to: Entity,
+    pub error: TransferError,
+}
+
+/// Move the items between the inventories
+pub(crate) fn handle_transfers(
+    item_registry: Res<ItemRegistry>,
+    mut inventories: Query<&mut Inventory>,
+    mut transfer_events: EventReader<TransferItemEvent>,
+    mut transferred_events: EventWriter<ItemsTransferredEvent>,
+    mut failed_events: EventWriter<TransferFailedEvent>,
+) {
+    for event in transfer_events.iter() {
+        if event.from == event.to || inventories.get(event.to).is_err() {
+            continue;
+        }
+        // Take the source out of the world, so both inventories can be borrowed at once
+        let mut from = match inventories.get_mut(event.from) {
+            Ok(mut from) => std::mem::take(&mut *from),
+            Err(_) => continue,
+        };
+        let id = from.get_item(event.slot).map(|stack| stack.id.clone());
+        let result = {
+            let mut to = inventories.get_mut(event.to).unwrap();
+            transfer(&mut from, event.slot, &mut to, event.count, &item_registry)
+        };
+        *inventories.get_mut(event.from).unwrap() = from;
+
+        match (result, id) {
+            (Ok(count), Some(item)) => transferred_events.send(ItemsTransferredEvent {
+                from: event.from,
+                to: event.to,
+                item,
+                count,
+            }),
+            (Err(error), _) => failed_events.send(TransferFailedEvent {
+                from: event.from,
+                to: event.to,
+                error,
+            }),
+            _ => {}
+        }
+    }
+}
+
+/// The panel of the container menu the cursor is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerPanel {
+    // The inventory of the player
+    Player,
+    // The inventory of the container
+    Container,
+}
+
+impl Default for ContainerPanel {
+    fn default() -> Self {
+        Self::Player
+    }
+}
+
+/// The marker component for the ui of the container menu
+#[derive(Debug, Default, Clone, Component)]
+pub struct ContainerMenuUi;
+
+/// The state of the container menu
+#[derive(Debug, Default, Clone)]
+pub struct ContainerMenuState {
+    // The container that is open
+    pub container: Option<Entity>,
+    // The player that opened the container
+    pub owner: Option<Entity>,
+    pub panel: ContainerPanel,
+    // The index of the selected stack in the panel
+    pub cursor: usize,
+    // The result of the last transfer
+    pub message: String,
+    // Whether the ui has to be rebuilt
+    pub dirty: bool,
+}
+
+/// An action the player can take in the container menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContainerAction {
+    Move(i32),
+    SwitchPanel,
+    TakeOne,
+    TakeStack,
+    Close,
+}
+
+/// Open the container the player touches and presses the interact button at
+pub(crate) fn open_containers(
+    players: Query<(Entity, &PlayerState, &CollidingEntities), With<Player>>,
+    containers: Query<Option<&Lock>, (With<Container>, With<Inventory>)>,
+    mut menu: ResMut<ContainerMenuState>,
+    mut gamestate: ResMut<State<GameState>>,
+) {
+    for (player, state, colliding_entities) in players.iter() {
+        if !state.interact_pressed {
+            continue;
+        }
+        // Locked containers have to be unlocked first
+        let container = colliding_entities.iter().find(|entity| {
+            containers
+                .get(**entity)
+                .map_or(false, |lock| lock.map_or(true, |lock| lock.is_open()))
+        });
+        if let Some(container) = container {
+            // Another state change like a game over can already be queued this frame
+            if *gamestate.current() != GameState::Container
+                && gamestate.set(GameState::Container).is_err()
+            {
+                return;
+            }
+            *menu = ContainerMenuState {
+                container: Some(*container),
+                owner: Some(player),
+                dirty: true,
+                ..Default::default()
+            };
+            return;
+        }
+    }
+}
+
+/// Removes the ui of the container menu
+pub(crate) fn close_container_menu(
+    mut commands: Commands,
+    mut menu: ResMut<ContainerMenuState>,
+    ui: Query<Entity, With<ContainerMenuUi>>,
+) {
+    ui.for_each(|entity| commands.entity(entity).despawn_recursive());
+    menu.container = None;
+    menu.owner = None;
+}
+
+/// Every action of the container menu, the one that closes it first
+const ACTIONS: [ContainerAction; 6] = [
+    ContainerAction::Close,
+    ContainerAction::Move(-1),
+    ContainerAction::Move(1),
+    ContainerAction::SwitchPanel,
+    ContainerAction::TakeOne,
+    ContainerAction::TakeStack,
+];
+
+/// The keys of the container menu actions
+const KEYS: [(KeyCode, ContainerAction); 13] = [
+    (KeyCode::W, ContainerAction::Move(-1)),
+    (KeyCode::Up, ContainerAction::Move(-1)),
+    (KeyCode::S, ContainerAction::Move(1)),
+    (KeyCode::Down, ContainerAction::Move(1)),
+    (KeyCode::A, ContainerAction::SwitchPanel),
+    (KeyCode::Left, ContainerAction::SwitchPanel),
+    (KeyCode::D, ContainerAction::SwitchPanel),
+    (KeyCode::Right, ContainerAction::SwitchPanel),
+    (KeyCode::Return, ContainerAction::TakeOne),
+    (KeyCode::Space, ContainerAction::TakeOne),
+    (KeyCode::R, ContainerAction::TakeStack),
+    (KeyCode::Escape, ContainerAction::Close),
+    (KeyCode::Q, ContainerAction::Close),
+];
+
+/// The gamepad buttons of the container menu actions
+const BUTTONS: [(GamepadButtonType, ContainerAction); 8] = [
+    (GamepadButtonType::DPadUp, ContainerAction::Move(-1)),
+    (GamepadButtonType::DPadDown, ContainerAction::Move(1)),
+    (GamepadButtonType::DPadLeft, ContainerAction::SwitchPanel),
+    (GamepadButtonType::DPadRight, ContainerAction::SwitchPanel),
+    (GamepadButtonType::South, ContainerAction::TakeOne),
+    (GamepadButtonType::West, ContainerAction::TakeStack),
+    (GamepadButtonType::East, ContainerAction::Close),
+    (GamepadButtonType::Select, ContainerAction::Close),
+];
+
+/// Handle the input of the container menu
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_container_menu_input(
+    mut input: MenuInput,
+    mut menu: ResMut<ContainerMenuState>,
+    mut gamestate: ResMut<State<GameState>>,
+    inventories: Query<&Inventory>,
+    mut transfer_events: EventWriter<TransferItemEvent>,
+) {
+    let actions = input.read(menu.owner, &ACTIONS, &KEYS, &BUTTONS);
+    if actions.is_empty() {
+        return;
+    }
+
+    let open = menu.owner.zip(menu.container).filter(|(owner, container)| {
+        inventories.get(*owner).is_ok() && inventories.get(*container).is_ok()
+    });
+    let (owner, container) = match open {
+        Some(open) => open,
+        None => {
+            if *gamestate.current() != GameState::Gameplay {
+                gamestate.set(GameState::Gameplay).unwrap();
+            }
+            return;
+        }
+    };
+
+    for action in actions {
+        // Move from the panel the cursor is in to the other one
+        let (from, to) = match menu.panel {
+            ContainerPanel::Player => (owner, container),
+            ContainerPanel::Container => (container, owner),
+        };
+        let inventory = inventories.get(from).unwrap();
+        let selected = inventory.stacks().nth(menu.cursor);
+        menu.dirty = true;
+
+        match action {
+            ContainerAction::Move(direction) => {
+                let rows = inventory.stacks().count();
+                let moved = menu.cursor as i32 + direction;
+                menu.cursor = moved.clamp(0, rows.saturating_sub(1) as i32) as usize;
+            }
+            ContainerAction::SwitchPanel => {
+                menu.panel = match menu.panel {
+                    ContainerPanel::Player => ContainerPanel::Container,
+                    ContainerPanel::Container => ContainerPanel::Player,
+                };
+                menu.cursor = 0;
+            }
+            ContainerAction::TakeOne | ContainerAction::TakeStack => {
+                if let Some((slot, stack)) = selected {
+                    transfer_events.send(TransferItemEvent {
+                        from,
+                        slot,
+                        to,
+                        count: if action == ContainerAction::TakeStack {
+                            stack.count
+                        } else {
+                            1
+                        },
+                    });
+                }
+            }
+            ContainerAction::Close => {
+                close_menu(&mut input, &mut gamestate);
+                return;
+            }
+        }
+    }
+}
+
+/// Rebuild the ui of the container menu when it or one of the inventories changed
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_container_menu(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    item_registry: Res<ItemRegistry>,
+    mut menu: ResMut<ContainerMenuState>,
+    inventories: Query<(&Inventory, ChangeTrackers<Inventory>)>,
+    containers: Query<&Container>,
+    mut transferred_events: EventReader<ItemsTransferredEvent>,
+    mut failed_events: EventReader<TransferFailedEvent>,
+    ui: Query<Entity, With<ContainerMenuUi>>,
+) {
+    let (owner, container) = match menu.owner.zip(menu.container) {
+        Some(open) => open,
+        None => return,
+    };
+    let ((owner_inventory, owner_tracker), (container_inventory, container_tracker), name) =
+        match (
+            inventories.get(owner),
+            inventories.get(container),
+            containers.get(container),
+        ) {
+            (Ok(owner), Ok(container), Ok(name)) => (owner, container, name.name.as_str()),
+            _ => return,
+        };
+
+    // Show the result of the last transfer
+    if let Some(event) = transferred_events.iter().last() {
+        let name = item_registry
+            .get(&event.item)
+            .map_or(event.item.as_str(), |item| item.name.as_str());
+        menu.message = format!("Moved {} x{}", name, event.count);
+        menu.dirty = true;
+    }
+    if let Some(event) = failed_events.iter().last() {
+        menu.message = format!("Can't move that: {:?}", event.error);
+        menu.dirty = true;
+    }
+    if !menu.dirty && !owner_tracker.is_changed() && !container_tracker.is_changed() {
+        return;
+    }
+    menu.dirty = false;
+    ui.for_each(|entity| commands.entity(entity).despawn_recursive());
+
+    let selected_inventory = match menu.panel {
+        ContainerPanel::Player => owner_inventory,
+        ContainerPanel::Container => container_inventory,
+    };
+    menu.cursor = menu
+        .cursor
+        .min(selected_inventory.stacks().count().saturating_sub(1));
+
+    let text = |value: &str, size: f32, color: Color| TextBundle {
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: fonts.fira_sans.clone(),
+                font_size: size,
+                color,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(ContainerMenuUi)
+        .with_children(|parent| {
+            // The two panels side by side
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    let panels = [
+                        (ContainerPanel::Player, "Inventory", owner_inventory),
+                        (ContainerPanel::Container, name, container_inventory),
+                    ];
+                    for (panel, title, inventory) in panels {
+                        let active = panel == menu.panel;
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::ColumnReverse,
+                                    size: Size::new(Val::Px(280.0), Val::Auto),
+                                    padding: Rect::all(Val::Px(16.0)),
+                                    margin: Rect::all(Val::Px(8.0)),
+                                    ..Default::default()
+                                },
+                                color: Color::BLACK.into(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                let title_color = if active { Color::WHITE } else { Color::GRAY };
+                                parent.spawn_bundle(text(title, 32.0, title_color));
+                                parent.spawn_bundle(text(
+                                    &format!(
+                                        "{}/{} slots",
+                                        inventory.stacks().count(),
+                                        inventory.capacity
+                                    ),
+                                    16.0,
+                                    Color::GRAY,
+                                ));
+
+                                // A row for each stack
+                                for (index, (_, stack)) in inventory.stacks().enumerate() {
+                                    let name = item_registry
+                                        .get(&stack.id)
+                                        .map_or(stack.id.as_str(), |item| item.name.as_str());
+                                    let color = if active && index == menu.cursor {
+                                        Color::YELLOW
+                                    } else {
+                                        Color::WHITE
+                                    };
+                                    parent.spawn_bundle(text(
+                                        &format!("{} x{}", name, stack.count),
+                                        20.0,
+                                        color,
+                                    ));
+                                }
+                                if inventory.stacks().next().is_none() {
+                                    parent.spawn_bundle(text("Empty", 20.0, Color::GRAY));
+                                }
+                            });
+                    }
+                });
+
+            // The result of the last transfer
+            if !menu.message.is_empty() {
+                parent.spawn_bundle(text(&menu.message, 16.0, Color::YELLOW));
+            }
+            parent.spawn_bundle(text(
+                "Enter move one, R move stack, A/D switch side, Esc close",
+                14.0,
+                Color::GRAY,
+            ));
+        });
+}
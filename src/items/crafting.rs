@@ -0,0 +1,330 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+use super::{
+    data::{merge_data_files, DataFile, DataFileLoader, DataHandles, HasId},
+    effect::ItemMessage,
+    inventory::Inventory,
+    ItemId, ItemRegistry,
+};
+use crate::prelude::AssetLoadingState;
+
+/// The folder the recipe files are loaded from
+pub const RECIPE_FOLDER: &str = "recipes";
+
+/// A number of units of an item in a recipe
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecipeItem {
+    pub item: ItemId,
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+/// Recipes take and make one unit unless they say otherwise
+fn default_count() -> u32 {
+    1
+}
+
+/// A recipe that turns input items into an output item
+#[derive(Debug, Clone, Deserialize)]
+pub struct Recipe {
+    pub id: String,
+    pub inputs: Vec<RecipeItem>,
+    pub output: RecipeItem,
+}
+
+/// Why a recipe couldn't be crafted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CraftError {
+    // The recipe doesn't exist
+    UnknownRecipe,
+    // An input or the output isn't in the item registry
+    UnknownItem,
+    // The inventory doesn't hold all the inputs
+    MissingInputs,
+    // The inventory has no room for the output
+    InventoryFull,
+}
+
+impl Recipe {
+    /// Check if the inventory holds all the inputs
+    pub fn can_craft(&self, inventory: &Inventory) -> bool {
+        self.inputs
+            .iter()
+            .all(|input| inventory.count(&input.item) >= input.count)
+    }
+
+    /// Take the inputs out of the inventory and put the output in
+    /// The inventory is left as it was if anything goes wrong
+    pub fn craft(
+        &self,
+        inventory: &mut Inventory,
+        item_registry: &ItemRegistry,
+    ) -> Result<(), CraftError> {
+        let output = item_registry
+            .get(&self.output.item)
+            .ok_or(CraftError::UnknownItem)?;
+        if !self.inputs.iter().all(|input| item_registry.contains_key(&input.item)) {
+            return Err(CraftError::UnknownItem);
+        }
+        if !self.can_craft(inventory) {
+            return Err(CraftError::MissingInputs);
+        }
+
+        // Craft into a copy, so a full inventory doesn't lose the inputs
+        let mut crafted = inventory.clone();
+        for input in &self.inputs {
+            crafted.remove_by_id(&input.item, input.count);
+        }
+        if crafted.add_item(output, self.output.count) > 0 {
+            return Err(CraftError::InventoryFull);
+        }
+        *inventory = crafted;
+        Ok(())
+    }
+}
+
+/// A file of recipes
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "9d4f6b2e-1c3a-4e58-b7d0-5a8e3f2c6d91"]
+pub struct RecipeFile {
+    pub recipes: Vec<Recipe>,
+    // The path of the file the recipes were loaded from
+    #[serde(skip)]
+    pub path: String,
+}
+
+impl HasId for Recipe {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl DataFile for RecipeFile {
+    type Entry = Recipe;
+
+    const KIND: &'static str = "recipe";
+    const FOLDER: &'static str = RECIPE_FOLDER;
+    const EXTENSIONS: &'static [&'static str] = &["recipes.ron"];
+
+    fn entries(&self) -> &[Recipe] {
+        &self.recipes
+    }
+
+    fn entries_mut(&mut self) -> &mut Vec<Recipe> {
+        &mut self.recipes
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn set_path(&mut self, path: String) {
+        self.path = path;
+    }
+
+    fn loaded_flag(state: &mut AssetLoadingState) -> &mut bool {
+        &mut state.recipes_loaded
+    }
+
+    fn validate(recipe: &Recipe) -> Result<(), String> {
+        if recipe.inputs.is_empty() {
+            return Err("it has no inputs".into());
+        }
+        let mut items = recipe.inputs.iter().chain(std::iter::once(&recipe.output));
+        if let Some(empty) = items.find(|item| item.count == 0) {
+            return Err(format!("it uses 0 of \"{}\"", empty.item));
+        }
+        Ok(())
+    }
+}
+
+/// Loads `.recipes.ron` files into [`RecipeFile`]s
+pub type RecipeLoader = DataFileLoader<RecipeFile>;
+
+/// The handles of the loaded recipe files
+pub type RecipeHandles = DataHandles<RecipeFile>;
+
+/// The known recipes, ordered by id
+#[derive(Debug, Default, Clone)]
+pub struct Recipes(pub Vec<Recipe>);
+
+impl Recipes {
+    /// Get a recipe by its id
+    pub fn get(&self, id: &str) -> Option<&Recipe> {
+        self.0.iter().find(|recipe| recipe.id == id)
+    }
+
+    /// Iterate over the recipes that can be crafted from an inventory
+    pub fn craftable<'a>(&'a self, inventory: &'a Inventory) -> impl Iterator<Item = &'a Recipe> {
+        self.0.iter().filter(move |recipe| recipe.can_craft(inventory))
+    }
+}
+
+/// An event that crafts a recipe from the inventory of an entity
+#[derive(Debug, Clone)]
+pub struct CraftItemEvent {
+    pub entity: Entity,
+    pub recipe: String,
+}
+
+/// An event that reports the result of crafting a recipe
+#[derive(Debug, Clone)]
+pub struct CraftedEvent {
+    pub entity: Entity,
+    pub recipe: String,
+    pub result: Result<(), CraftError>,
+}
+
+/// Rebuild the recipes whenever a recipe file or the item registry changes
+/// The recipes that use unknown items are skipped
+pub(crate) fn rebuild_recipes(
+    files: Res<Assets<RecipeFile>>,
+    item_registry: Res<ItemRegistry>,
+    mut file_events: EventReader<AssetEvent<RecipeFile>>,
+    mut recipes: ResMut<Recipes>,
+) {
+    if file_events.iter().count() == 0 && !item_registry.is_changed() {
+        return;
+    }
+
+    recipes.0.clear();
+    for (path, recipe) in merge_data_files(&files) {
+        let unknown = recipe
+            .inputs
+            .iter()
+            .chain(std::iter::once(&recipe.output))
+            .find(|input| !item_registry.contains_key(&input.item));
+        if let Some(unknown) = unknown {
+            error!(
+                "{}: skipping the recipe \"{}\": the item \"{}\" doesn't exist",
+                path, recipe.id, unknown.item
+            );
+            continue;
+        }
+        recipes.0.push(recipe.clone());
+    }
+    recipes.0.sort_by(|a, b| a.id.cmp(&b.id));
+    info!("Registered {} recipes", recipes.0.len());
+}
+
+/// Craft the recipes
+pub(crate) fn handle_crafting(
+    item_registry: Res<ItemRegistry>,
+    recipes: Res<Recipes>,
+    mut inventories: Query<&mut Inventory>,
+    mut craft_events: EventReader<CraftItemEvent>,
+    mut crafted_events: EventWriter<CraftedEvent>,
+) {
+    for event in craft_events.iter() {
+        let mut inventory = match inventories.get_mut(event.entity) {
+            Ok(inventory) => inventory,
+            Err(_) => continue,
+        };
+        let result = recipes
+            .get(&event.recipe)
+            .ok_or(CraftError::UnknownRecipe)
+            .and_then(|recipe| recipe.craft(&mut inventory, &item_registry));
+        crafted_events.send(CraftedEvent {
+            entity: event.entity,
+            recipe: event.recipe.clone(),
+            result,
+        });
+    }
+}
+
+/// Show the result of the last craft on screen
+pub(crate) fn show_craft_messages(
+    item_registry: Res<ItemRegistry>,
+    recipes: Res<Recipes>,
+    mut message: ResMut<ItemMessage>,
+    mut crafted_events: EventReader<CraftedEvent>,
+) {
+    let event = match crafted_events.iter().last() {
+        Some(event) => event,
+        None => return,
+    };
+    message.text = match (event.result, recipes.get(&event.recipe)) {
+        (Ok(()), Some(recipe)) => {
+            let name = item_registry
+                .get(&recipe.output.item)
+                .map_or(recipe.output.item.as_str(), |item| item.name.as_str());
+            format!("Made {} x{}", name, recipe.output.count)
+        }
+        (Ok(()), None) => format!("Made {}", event.recipe),
+        (Err(err), _) => format!("Can't craft that: {:?}", err),
+    };
+    message.remaining = 2.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::{test_item, ItemCategory};
+
+    fn stew_recipe() -> Recipe {
+        Recipe {
+            id: "bean_stew".into(),
+            inputs: vec![RecipeItem {
+                item: ItemId::new("bean"),
+                count: 2,
+            }],
+            output: RecipeItem {
+                item: ItemId::new("bean_stew"),
+                count: 1,
+            },
+        }
+    }
+
+    fn registry() -> ItemRegistry {
+        let mut item_registry = ItemRegistry::default();
+        for item in [
+            test_item("bean", ItemCategory::Food, 10),
+            test_item("bean_stew", ItemCategory::Food, 10),
+            test_item("coin", ItemCategory::Misc, 10),
+        ] {
+            item_registry.insert(item.id.clone(), item);
+        }
+        item_registry
+    }
+
+    #[test]
+    fn craft_takes_the_inputs_and_adds_the_output() {
+        let item_registry = registry();
+        let mut inventory = Inventory::with_capacity(2);
+        inventory.add_item(&item_registry["bean"], 3);
+
+        assert_eq!(stew_recipe().craft(&mut inventory, &item_registry), Ok(()));
+        assert_eq!(inventory.count("bean"), 1);
+        assert_eq!(inventory.count("bean_stew"), 1);
+    }
+
+    #[test]
+    fn craft_keeps_the_inputs_when_the_inventory_is_full() {
+        let item_registry = registry();
+        let mut inventory = Inventory::with_capacity(2);
+        inventory.add_item(&item_registry["bean"], 3);
+        inventory.add_item(&item_registry["coin"], 1);
+
+        assert_eq!(
+            stew_recipe().craft(&mut inventory, &item_registry),
+            Err(CraftError::InventoryFull)
+        );
+        assert_eq!(inventory.count("bean"), 3);
+        assert_eq!(inventory.count("coin"), 1);
+        assert_eq!(inventory.count("bean_stew"), 0);
+    }
+
+    #[test]
+    fn craft_fails_without_the_inputs() {
+        let item_registry = registry();
+        let mut inventory = Inventory::with_capacity(2);
+        inventory.add_item(&item_registry["bean"], 1);
+
+        assert_eq!(
+            stew_recipe().craft(&mut inventory, &item_registry),
+            Err(CraftError::MissingInputs)
+        );
+        assert_eq!(inventory.count("bean"), 1);
+    }
+}
@@ -0,0 +1,232 @@
+use bevy::{
+    asset::{Asset, AssetLoader, BoxedFuture, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+};
+use hashbrown::HashMap;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+use crate::prelude::AssetLoadingState;
+
+/// An entry of a data file that is known by its id
+pub trait HasId {
+    /// The id the entry is registered under
+    fn id(&self) -> &str;
+}
+
+/// A `.ron` file of entries that is loaded from a folder of the assets
+pub trait DataFile: Asset + DeserializeOwned {
+    type Entry: HasId + Send + Sync;
+
+    // What an entry is called in the log, like "item"
+    const KIND: &'static str;
+    // The folder the files are loaded from
+    const FOLDER: &'static str;
+    // The extensions of the files, like "items.ron"
+    const EXTENSIONS: &'static [&'static str];
+
+    /// The entries of the file
+    fn entries(&self) -> &[Self::Entry];
+
+    /// The entries of the file, to replace them with the valid ones
+    fn entries_mut(&mut self) -> &mut Vec<Self::Entry>;
+
+    /// The path of the file, relative to the assets folder
+    fn path(&self) -> &str;
+
+    /// Set the path the file was loaded from
+    fn set_path(&mut self, path: String);
+
+    /// The flag that is set once every file of this kind is done loading
+    fn loaded_flag(state: &mut AssetLoadingState) -> &mut bool;
+
+    /// Check an entry, the invalid ones are skipped
+    fn validate(_entry: &Self::Entry) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The assets an entry uses, the entries with missing assets are skipped
+    fn asset_paths(_entry: &Self::Entry) -> Vec<&str> {
+        vec![]
+    }
+}
+
+/// Loads the files of a [`DataFile`], skipping the invalid, duplicate and incomplete entries
+/// Only a file that can't be parsed fails to load
+pub struct DataFileLoader<F>(PhantomData<fn() -> F>);
+
+impl<F> Default for DataFileLoader<F> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<F: DataFile> AssetLoader for DataFileLoader<F> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let path = load_context.path().display().to_string();
+            let text = std::str::from_utf8(bytes)
+                .map_err(|err| anyhow::anyhow!("{}: the file is not valid utf-8: {}", path, err))?;
+            let mut file: F = ron::from_str(text).map_err(|err| {
+                anyhow::anyhow!(
+                    "{}:{}:{}: {}",
+                    path,
+                    err.position.line,
+                    err.position.col,
+                    err.code
+                )
+            })?;
+
+            let entries = std::mem::take(file.entries_mut());
+            let ids = entries
+                .iter()
+                .map(|entry| entry.id().to_string())
+                .collect::<Vec<_>>();
+            let mut kept: Vec<F::Entry> = vec![];
+            for (index, entry) in entries.into_iter().enumerate() {
+                // Point the errors at the line the entry is defined at
+                let nth = ids[..index].iter().filter(|id| *id == entry.id()).count();
+                let location = match entry_line(text, entry.id(), nth) {
+                    Some(line) => format!("{}:{}", path, line),
+                    None => path.clone(),
+                };
+
+                if kept.iter().any(|other| other.id() == entry.id()) {
+                    error!(
+                        "{}: the {} id \"{}\" is already defined, skipping it",
+                        location,
+                        F::KIND,
+                        entry.id()
+                    );
+                    continue;
+                }
+                if let Err(err) = F::validate(&entry) {
+                    error!("{}: skipping the {} \"{}\": {}", location, F::KIND, entry.id(), err);
+                    continue;
+                }
+                let mut missing = None;
+                for asset in F::asset_paths(&entry) {
+                    if load_context.read_asset_bytes(asset).await.is_err() {
+                        missing = Some(asset.to_string());
+                        break;
+                    }
+                }
+                if let Some(asset) = missing {
+                    error!(
+                        "{}: skipping the {} \"{}\": the asset \"{}\" doesn't exist",
+                        location,
+                        F::KIND,
+                        entry.id(),
+                        asset
+                    );
+                    continue;
+                }
+                kept.push(entry);
+            }
+            *file.entries_mut() = kept;
+            file.set_path(path);
+
+            load_context.set_default_asset(LoadedAsset::new(file));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        F::EXTENSIONS
+    }
+}
+
+/// Find the line of the `nth` entry that is defined with an id, by looking for its `id: "..."`
+/// in the text of the file
+fn entry_line(text: &str, id: &str, nth: usize) -> Option<usize> {
+    let quoted = format!("\"{}\"", id);
+    text.match_indices(&quoted)
+        .map(|(index, _)| index)
+        .filter(|index| {
+            let field = match text[..*index].trim_end().strip_suffix(':') {
+                Some(before) => before.trim_end(),
+                None => return false,
+            };
+            // Skip the fields that only end with "id", like "valid"
+            field.strip_suffix("id").map_or(false, |before| {
+                !before.ends_with(|c: char| c.is_alphanumeric() || c == '_')
+            })
+        })
+        .nth(nth)
+        .map(|index| text[..index].matches('\n').count() + 1)
+}
+
+/// The handles of the loaded files of a [`DataFile`]
+pub struct DataHandles<F> {
+    pub handles: Vec<HandleUntyped>,
+    marker: PhantomData<fn() -> F>,
+}
+
+impl<F> Default for DataHandles<F> {
+    fn default() -> Self {
+        Self {
+            handles: vec![],
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Start loading the files of a [`DataFile`]
+pub(crate) fn load_data_files<F: DataFile>(
+    asset_server: Res<AssetServer>,
+    mut handles: ResMut<DataHandles<F>>,
+) {
+    info!("Loading {} files", F::KIND);
+    match asset_server.load_folder(F::FOLDER) {
+        Ok(loaded) => handles.handles = loaded,
+        Err(err) => error!("Failed to load the {} files: {:?}", F::KIND, err),
+    }
+}
+
+/// Marks the files of a [`DataFile`] as loaded once every one of them is done loading
+pub(crate) fn check_data_files<F: DataFile>(
+    asset_server: Res<AssetServer>,
+    handles: Res<DataHandles<F>>,
+    mut state: ResMut<AssetLoadingState>,
+) {
+    let loaded = F::loaded_flag(&mut *state);
+    if *loaded {
+        return;
+    }
+    // Files that failed to load already reported their errors
+    match asset_server.get_group_load_state(handles.handles.iter().map(|handle| handle.id)) {
+        LoadState::Loaded | LoadState::Failed => *loaded = true,
+        _ => {}
+    }
+}
+
+/// The entries of all the loaded files with the path of their file
+/// The files are sorted by path so the same entry always wins when an id is defined twice
+pub fn merge_data_files<F: DataFile>(files: &Assets<F>) -> Vec<(&str, &F::Entry)> {
+    let mut files = files.iter().map(|(_, file)| file).collect::<Vec<_>>();
+    files.sort_by(|a, b| a.path().cmp(b.path()));
+
+    let mut origins: HashMap<&str, &str> = HashMap::new();
+    let mut entries = vec![];
+    for file in files {
+        for entry in file.entries() {
+            if let Some(other_path) = origins.get(entry.id()) {
+                error!(
+                    "{}: the {} id \"{}\" is already defined in {}",
+                    file.path(),
+                    F::KIND,
+                    entry.id(),
+                    other_path
+                );
+                continue;
+            }
+            origins.insert(entry.id(), file.path());
+            entries.push((file.path(), entry));
+        }
+    }
+    entries
+}
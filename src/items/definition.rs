@@ -0,0 +1,181 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+use super::{
+    data::{merge_data_files, DataFile, DataFileLoader, DataHandles, HasId},
+    effect::ItemEffect,
+    equipment::{EquipSlot, StatModifier},
+    pickup::PickupMode,
+    ItemCategory, ItemDescriptor, ItemId, ItemRegistry,
+};
+use crate::prelude::{AbilityGrant, AssetLoadingState, MeleeAttack, RangedAttack};
+
+/// The folder the item definition files are loaded from
+pub const ITEM_DEFINITION_FOLDER: &str = "items";
+
+/// The definition of an item as it is written in an item file
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemDefinition {
+    pub id: ItemId,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    // The path of the icon, relative to the assets folder
+    pub icon: String,
+    #[serde(default)]
+    pub category: ItemCategory,
+    #[serde(default)]
+    pub grants: Option<AbilityGrant>,
+    // The number of units that fit in one inventory slot
+    #[serde(default = "default_max_stack")]
+    pub max_stack: u32,
+    #[serde(default)]
+    pub effects: Vec<ItemEffect>,
+    #[serde(default)]
+    pub consumable: bool,
+    #[serde(default)]
+    pub pickup: PickupMode,
+    #[serde(default)]
+    pub equip: Option<EquipSlot>,
+    #[serde(default)]
+    pub modifiers: Vec<StatModifier>,
+    // The coins a shop pays for the item, it can't be sold without one
+    #[serde(default)]
+    pub sell_price: Option<u32>,
+    // The coins picking the item up puts in the wallet, instead of the item
+    #[serde(default)]
+    pub coins: u32,
+    // The melee attack of a weapon
+    #[serde(default)]
+    pub attack: Option<MeleeAttack>,
+    // The ranged attack of a weapon
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+}
+
+/// Items don't stack unless their definition says so
+fn default_max_stack() -> u32 {
+    1
+}
+
+/// A file of item definitions
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "5b0a3c52-7a52-4d8e-9a3b-0f4b6b1c9e21"]
+pub struct ItemDefinitions {
+    pub items: Vec<ItemDefinition>,
+    // The path of the file the definitions were loaded from
+    #[serde(skip)]
+    pub path: String,
+}
+
+impl HasId for ItemDefinition {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl DataFile for ItemDefinitions {
+    type Entry = ItemDefinition;
+
+    const KIND: &'static str = "item";
+    const FOLDER: &'static str = ITEM_DEFINITION_FOLDER;
+    const EXTENSIONS: &'static [&'static str] = &["items.ron"];
+
+    fn entries(&self) -> &[ItemDefinition] {
+        &self.items
+    }
+
+    fn entries_mut(&mut self) -> &mut Vec<ItemDefinition> {
+        &mut self.items
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn set_path(&mut self, path: String) {
+        self.path = path;
+    }
+
+    fn loaded_flag(state: &mut AssetLoadingState) -> &mut bool {
+        &mut state.items_loaded
+    }
+
+    fn validate(item: &ItemDefinition) -> Result<(), String> {
+        if item.max_stack == 0 {
+            return Err("it has a max stack of 0".into());
+        }
+        if let Some(attack) = &item.attack {
+            if item.category != ItemCategory::Weapon {
+                return Err("it has an attack but isn't a weapon".into());
+            }
+            if attack.active == 0 {
+                return Err("its attack is active for 0 frames".into());
+            }
+        }
+        if let Some(ranged) = &item.ranged {
+            if item.category != ItemCategory::Weapon {
+                return Err("it has a ranged attack but isn't a weapon".into());
+            }
+            if item.attack.is_some() {
+                return Err("it has both a melee and a ranged attack".into());
+            }
+            if ranged.lifetime <= 0.0 {
+                return Err("its projectiles have no lifetime".into());
+            }
+        }
+        Ok(())
+    }
+
+    fn asset_paths(item: &ItemDefinition) -> Vec<&str> {
+        let mut paths = vec![item.icon.as_str()];
+        if let Some(ranged) = &item.ranged {
+            paths.push(&ranged.projectile);
+        }
+        paths
+    }
+}
+
+/// Loads `.items.ron` files into [`ItemDefinitions`]
+pub type ItemDefinitionsLoader = DataFileLoader<ItemDefinitions>;
+
+/// The handles of the loaded item definition files
+pub type ItemDefinitionHandles = DataHandles<ItemDefinitions>;
+
+/// Rebuild the item registry whenever a definition file is loaded, changed or removed
+pub(crate) fn rebuild_item_registry(
+    asset_server: Res<AssetServer>,
+    definitions: Res<Assets<ItemDefinitions>>,
+    mut definition_events: EventReader<AssetEvent<ItemDefinitions>>,
+    mut item_registry: ResMut<ItemRegistry>,
+) {
+    if definition_events.iter().count() == 0 {
+        return;
+    }
+
+    item_registry.clear();
+    for (_, item) in merge_data_files(&definitions) {
+        item_registry.insert(
+            item.id.clone(),
+            ItemDescriptor {
+                id: item.id.clone(),
+                name: item.name.clone(),
+                description: item.description.clone(),
+                icon: asset_server.load(item.icon.as_str()),
+                category: item.category,
+                grants: item.grants,
+                max_stack: item.max_stack,
+                effects: item.effects.clone(),
+                consumable: item.consumable,
+                pickup: item.pickup,
+                equip: item.equip,
+                modifiers: item.modifiers.clone(),
+                sell_price: item.sell_price,
+                coins: item.coins,
+                attack: item.attack.clone(),
+                ranged: item.ranged.clone(),
+            },
+        );
+    }
+    info!("Registered {} items", item_registry.len());
+}
@@ -0,0 +1,311 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::Deserialize;
+
+use super::{
+    equipment::EquipItemEvent, inventory::Inventory, throw::DropItemEvent, ItemId, ItemRegistry,
+};
+use crate::{
+    health::{Health, Invulnerable},
+    player::{
+        abilities::{Abilities, Ability, AbilityUnlockedEvent, GrantTrigger},
+        buffs::{Buff, BuffKind, Buffs},
+        component::PlayerState,
+    },
+    simulation::SimulationTime,
+};
+
+/// What an item does when it is used
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum ItemEffect {
+    // Heals the user
+    Heal(u32),
+    // Gives the user a timed buff
+    Buff {
+        kind: BuffKind,
+        amount: f32,
+        duration: f32,
+    },
+}
+
+/// An event that uses the item in an inventory slot
+#[derive(Debug, Clone)]
+pub struct UseItemEvent {
+    pub user: Entity,
+    pub slot: usize,
+}
+
+/// An event that reports the result of using an item
+#[derive(Debug, Clone)]
+pub struct ItemUsedEvent {
+    pub user: Entity,
+    pub item: ItemId,
+    pub success: bool,
+    pub message: String,
+}
+
+/// A pending use of an item
+/// The effects of the item are added as components next to it and applied by their own systems
+#[derive(Debug, Clone, Component)]
+pub struct ItemUse {
+    pub user: Entity,
+    pub slot: usize,
+    pub item: ItemId,
+    // Whether the item is removed once it had an effect
+    pub consumable: bool,
+    // Whether any of the effects did something
+    pub applied: bool,
+    // The messages of the effects, shown to the player
+    pub messages: Vec<String>,
+}
+
+/// Heals the user of an [`ItemUse`]
+#[derive(Debug, Clone, Component)]
+pub struct HealEffect(pub u32);
+
+/// Buffs the user of an [`ItemUse`]
+#[derive(Debug, Clone, Component)]
+pub struct BuffEffect(pub Vec<Buff>);
+
+/// Unlocks an ability for the user of an [`ItemUse`]
+#[derive(Debug, Clone, Component)]
+pub struct GrantEffect(pub Ability);
+
+/// The last item message that is shown on screen
+#[derive(Debug, Default, Clone)]
+pub struct ItemMessage {
+    pub text: String,
+    // The time left in seconds
+    pub remaining: f32,
+}
+
+/// Use or throw the selected item when a player presses the button, and cycle the selected slot
+pub(crate) fn use_item_input(
+    mut players: Query<(Entity, &mut PlayerState, &mut Inventory)>,
+    mut use_events: EventWriter<UseItemEvent>,
+    mut drop_events: EventWriter<DropItemEvent>,
+) {
+    players.for_each_mut(|(entity, mut state, mut inventory)| {
+        let use_pressed = state.input.is_using_item && !state.use_held;
+        let cycle_pressed = state.input.is_cycling_item && !state.cycle_held;
+        let throw_pressed = state.input.is_throwing && !state.throw_held;
+        state.use_held = state.input.is_using_item;
+        state.cycle_held = state.input.is_cycling_item;
+        state.throw_held = state.input.is_throwing;
+
+        if cycle_pressed {
+            inventory.select_next();
+        }
+        if use_pressed {
+            use_events.send(UseItemEvent {
+                user: entity,
+                slot: inventory.selected,
+            });
+        }
+        if throw_pressed {
+            drop_events.send(DropItemEvent {
+                entity,
+                slot: inventory.selected,
+                count: 1,
+                throw: true,
+            });
+        }
+    });
+}
+
+/// Spawn an [`ItemUse`] with the effect components of the used item
+pub(crate) fn dispatch_item_uses(
+    mut commands: Commands,
+    item_registry: Res<ItemRegistry>,
+    inventories: Query<&Inventory>,
+    mut use_events: EventReader<UseItemEvent>,
+    mut used_events: EventWriter<ItemUsedEvent>,
+    mut equip_events: EventWriter<EquipItemEvent>,
+) {
+    for event in use_events.iter() {
+        let stack = match inventories
+            .get(event.user)
+            .ok()
+            .and_then(|inventory| inventory.get_item(event.slot))
+        {
+            Some(stack) => stack,
+            None => continue,
+        };
+        let item = match item_registry.get(&stack.id) {
+            Some(item) => item,
+            None => continue,
+        };
+        // Using an equippable item puts it on
+        if item.equip.is_some() {
+            equip_events.send(EquipItemEvent {
+                entity: event.user,
+                slot: event.slot,
+            });
+            continue;
+        }
+        let grant = item.grants.filter(|grant| grant.on == GrantTrigger::Use);
+        if item.effects.is_empty() && grant.is_none() {
+            used_events.send(ItemUsedEvent {
+                user: event.user,
+                item: item.id.clone(),
+                success: false,
+                message: format!("The {} can't be used", item.name),
+            });
+            continue;
+        }
+
+        let mut item_use = commands.spawn();
+        item_use.insert(ItemUse {
+            user: event.user,
+            slot: event.slot,
+            item: item.id.clone(),
+            consumable: item.consumable,
+            applied: false,
+            messages: vec![],
+        });
+
+        // Add a component for each effect
+        let mut heal = 0;
+        let mut buffs = vec![];
+        for effect in &item.effects {
+            match effect {
+                ItemEffect::Heal(amount) => heal += amount,
+                ItemEffect::Buff {
+                    kind,
+                    amount,
+                    duration,
+                } => buffs.push(Buff {
+                    kind: *kind,
+                    amount: *amount,
+                    remaining: *duration,
+                }),
+            }
+        }
+        if heal > 0 {
+            item_use.insert(HealEffect(heal));
+        }
+        if !buffs.is_empty() {
+            item_use.insert(BuffEffect(buffs));
+        }
+        if let Some(grant) = grant {
+            item_use.insert(GrantEffect(grant.ability));
+        }
+    }
+}
+
+/// Apply the heal effects
+pub(crate) fn apply_heal_effects(
+    mut uses: Query<(&mut ItemUse, &HealEffect)>,
+    mut healths: Query<&mut Health>,
+) {
+    uses.for_each_mut(|(mut item_use, effect)| {
+        if let Ok(mut health) = healths.get_mut(item_use.user) {
+            let healed = health.heal(effect.0);
+            if healed > 0 {
+                item_use.applied = true;
+                item_use.messages.push(format!("Healed {}", healed));
+            } else {
+                item_use.messages.push("Already at full health".into());
+            }
+        }
+    });
+}
+
+/// Apply the buff effects
+pub(crate) fn apply_buff_effects(
+    mut commands: Commands,
+    mut uses: Query<(&mut ItemUse, &BuffEffect)>,
+    mut buffs: Query<&mut Buffs>,
+) {
+    uses.for_each_mut(|(mut item_use, effect)| {
+        if let Ok(mut buffs) = buffs.get_mut(item_use.user) {
+            for buff in &effect.0 {
+                if buff.kind == BuffKind::Invulnerability {
+                    commands.entity(item_use.user).insert(Invulnerable {
+                        remaining: buff.remaining,
+                    });
+                }
+                buffs.add(buff.clone());
+                item_use
+                    .messages
+                    .push(format!("{:?} boost for {}s", buff.kind, buff.remaining));
+            }
+            item_use.applied = true;
+        }
+    });
+}
+
+/// Apply the ability grant effects
+pub(crate) fn apply_grant_effects(
+    mut uses: Query<(&mut ItemUse, &GrantEffect)>,
+    mut abilities: Query<&mut Abilities>,
+    mut unlock_events: EventWriter<AbilityUnlockedEvent>,
+) {
+    uses.for_each_mut(|(mut item_use, effect)| {
+        if let Ok(mut abilities) = abilities.get_mut(item_use.user) {
+            if abilities.grant(effect.0) {
+                unlock_events.send(AbilityUnlockedEvent {
+                    entity: item_use.user,
+                    ability: effect.0,
+                });
+                item_use.applied = true;
+                item_use.messages.push(format!("Unlocked {:?}", effect.0));
+            } else {
+                item_use.messages.push(format!("{:?} is already unlocked", effect.0));
+            }
+        }
+    });
+}
+
+/// Remove the used up items and report the results
+pub(crate) fn finish_item_uses(
+    mut commands: Commands,
+    uses: Query<(Entity, &ItemUse)>,
+    mut inventories: Query<&mut Inventory>,
+    mut used_events: EventWriter<ItemUsedEvent>,
+) {
+    uses.for_each(|(entity, item_use)| {
+        // Consumables are only used up when they did something
+        if item_use.applied && item_use.consumable {
+            if let Ok(mut inventory) = inventories.get_mut(item_use.user) {
+                // Only remove the item if it is still in the slot
+                if inventory
+                    .get_item(item_use.slot)
+                    .map_or(false, |stack| stack.id == item_use.item)
+                {
+                    inventory.remove_item(item_use.slot, 1);
+                }
+            }
+        }
+        used_events.send(ItemUsedEvent {
+            user: item_use.user,
+            item: item_use.item.clone(),
+            success: item_use.applied,
+            message: item_use.messages.join(", "),
+        });
+        commands.entity(entity).despawn();
+    });
+}
+
+/// Show the result of the last item use
+pub(crate) fn show_item_messages(
+    time: Res<SimulationTime>,
+    mut message: ResMut<ItemMessage>,
+    mut used_events: EventReader<ItemUsedEvent>,
+    mut egui: ResMut<EguiContext>,
+) {
+    if let Some(event) = used_events.iter().last() {
+        message.text = event.message.clone();
+        message.remaining = 2.0;
+    }
+    if message.remaining <= 0.0 {
+        return;
+    }
+    message.remaining -= time.delta_seconds();
+
+    egui::Area::new("item_message")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::Vec2::new(0.0, -32.0))
+        .show(egui.ctx_mut(), |ui| {
+            ui.label(message.text.as_str());
+        });
+}
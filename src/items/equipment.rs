@@ -0,0 +1,285 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{effect::ItemUsedEvent, inventory::Inventory, ItemId, ItemRegistry};
+use crate::player::component::Player;
+
+/// The slot an item is equipped in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum EquipSlot {
+    Weapon,
+    Head,
+    Accessory,
+    Charm,
+}
+
+impl EquipSlot {
+    /// All the equipment slots, in the order they are shown in
+    pub const ALL: [EquipSlot; 4] = [
+        EquipSlot::Weapon,
+        EquipSlot::Head,
+        EquipSlot::Accessory,
+        EquipSlot::Charm,
+    ];
+}
+
+/// A special effect that is turned on while an item is equipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SpecialEffect {
+    // Shows the secrets of the level
+    RevealSecrets,
+}
+
+/// A change to the stats of the entity that has an item equipped
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum StatModifier {
+    // Multiplies the horizontal acceleration
+    Speed(f32),
+    // Multiplies the jump velocity
+    Jump(f32),
+    // Adds to the damage dealt
+    Damage(u32),
+    // Turns on a special effect
+    Special(SpecialEffect),
+}
+
+/// The items an entity has equipped, by the id of the item
+#[derive(Debug, Default, Clone, Component)]
+pub struct Equipment {
+    pub weapon: Option<ItemId>,
+    pub head: Option<ItemId>,
+    pub accessory: Option<ItemId>,
+    pub charm: Option<ItemId>,
+}
+
+/// Why an item couldn't be equipped or unequipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EquipError {
+    // There is no item in the inventory slot
+    EmptySlot,
+    // The item can't be equipped
+    NotEquippable,
+    // The inventory has no room for the item that is taken off
+    InventoryFull,
+}
+
+impl Equipment {
+    /// Get the id of the item in a slot
+    pub fn get(&self, slot: EquipSlot) -> Option<&ItemId> {
+        match slot {
+            EquipSlot::Weapon => self.weapon.as_ref(),
+            EquipSlot::Head => self.head.as_ref(),
+            EquipSlot::Accessory => self.accessory.as_ref(),
+            EquipSlot::Charm => self.charm.as_ref(),
+        }
+    }
+
+    /// Get the mutable item in a slot
+    fn slot_mut(&mut self, slot: EquipSlot) -> &mut Option<ItemId> {
+        match slot {
+            EquipSlot::Weapon => &mut self.weapon,
+            EquipSlot::Head => &mut self.head,
+            EquipSlot::Accessory => &mut self.accessory,
+            EquipSlot::Charm => &mut self.charm,
+        }
+    }
+
+    /// Iterate over the equipped items
+    pub fn iter(&self) -> impl Iterator<Item = (EquipSlot, &ItemId)> {
+        EquipSlot::ALL
+            .into_iter()
+            .filter_map(move |slot| self.get(slot).map(|id| (slot, id)))
+    }
+
+    /// Move one unit of the item in an inventory slot into its equipment slot
+    /// The item that was equipped before goes back into the inventory
+    /// Returns the slot the item was equipped in
+    pub fn equip(
+        &mut self,
+        inventory: &mut Inventory,
+        index: usize,
+        item_registry: &ItemRegistry,
+    ) -> Result<EquipSlot, EquipError> {
+        let stack = inventory.get_item(index).ok_or(EquipError::EmptySlot)?;
+        let item = item_registry
+            .get(&stack.id)
+            .ok_or(EquipError::NotEquippable)?;
+        let slot = item.equip.ok_or(EquipError::NotEquippable)?;
+
+        inventory.remove_item(index, 1);
+        if let Some(previous) = self.slot_mut(slot).replace(item.id.clone()) {
+            let fits = item_registry
+                .get(&previous)
+                .map_or(false, |previous| inventory.add_item(previous, 1) == 0);
+            if !fits {
+                // Undo the swap
+                *self.slot_mut(slot) = Some(previous);
+                inventory.add_item(item, 1);
+                return Err(EquipError::InventoryFull);
+            }
+        }
+        Ok(slot)
+    }
+
+    /// Move the item in an equipment slot back into the inventory
+    pub fn unequip(
+        &mut self,
+        inventory: &mut Inventory,
+        slot: EquipSlot,
+        item_registry: &ItemRegistry,
+    ) -> Result<(), EquipError> {
+        let id = self.get(slot).ok_or(EquipError::EmptySlot)?;
+        let item = item_registry.get(id).ok_or(EquipError::NotEquippable)?;
+        if inventory.add_item(item, 1) > 0 {
+            return Err(EquipError::InventoryFull);
+        }
+        *self.slot_mut(slot) = None;
+        Ok(())
+    }
+}
+
+/// The combined stat modifiers of the equipped items
+#[derive(Debug, Clone, Component)]
+pub struct StatModifiers {
+    // The multiplier for the horizontal acceleration
+    pub speed: f32,
+    // The multiplier for the jump velocity
+    pub jump: f32,
+    // The bonus to the damage dealt
+    pub damage: u32,
+    // The special effects that are turned on
+    pub specials: Vec<SpecialEffect>,
+}
+
+impl Default for StatModifiers {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            jump: 1.0,
+            damage: 0,
+            specials: vec![],
+        }
+    }
+}
+
+impl StatModifiers {
+    /// Check if a special effect is turned on
+    pub fn has(&self, special: SpecialEffect) -> bool {
+        self.specials.contains(&special)
+    }
+}
+
+/// Marks a level object that is only visible with [`SpecialEffect::RevealSecrets`]
+#[derive(Debug, Default, Clone, Component)]
+pub struct Secret;
+
+/// An event that equips the item in an inventory slot
+#[derive(Debug, Clone)]
+pub struct EquipItemEvent {
+    pub entity: Entity,
+    pub slot: usize,
+}
+
+/// An event that takes off the item in an equipment slot
+#[derive(Debug, Clone)]
+pub struct UnequipItemEvent {
+    pub entity: Entity,
+    pub slot: EquipSlot,
+}
+
+/// Equip and unequip items
+pub(crate) fn handle_equip_events(
+    item_registry: Res<ItemRegistry>,
+    mut holders: Query<(&mut Inventory, &mut Equipment)>,
+    mut equip_events: EventReader<EquipItemEvent>,
+    mut unequip_events: EventReader<UnequipItemEvent>,
+    mut used_events: EventWriter<ItemUsedEvent>,
+) {
+    for event in equip_events.iter() {
+        if let Ok((mut inventory, mut equipment)) = holders.get_mut(event.entity) {
+            let id = match inventory.get_item(event.slot) {
+                Some(stack) => stack.id.clone(),
+                None => continue,
+            };
+            let result = equipment.equip(&mut inventory, event.slot, &item_registry);
+            used_events.send(ItemUsedEvent {
+                user: event.entity,
+                item: id.clone(),
+                success: result.is_ok(),
+                message: match result {
+                    Ok(slot) => format!("Equipped {} as {:?}", item_name(&item_registry, &id), slot),
+                    Err(err) => format!("Can't equip {}: {:?}", item_name(&item_registry, &id), err),
+                },
+            });
+        }
+    }
+    for event in unequip_events.iter() {
+        if let Ok((mut inventory, mut equipment)) = holders.get_mut(event.entity) {
+            let id = match equipment.get(event.slot) {
+                Some(id) => id.clone(),
+                None => continue,
+            };
+            let result = equipment.unequip(&mut inventory, event.slot, &item_registry);
+            used_events.send(ItemUsedEvent {
+                user: event.entity,
+                item: id.clone(),
+                success: result.is_ok(),
+                message: match result {
+                    Ok(()) => format!("Took off {}", item_name(&item_registry, &id)),
+                    Err(err) => format!("Can't take off {}: {:?}", item_name(&item_registry, &id), err),
+                },
+            });
+        }
+    }
+}
+
+/// Get the display name of an item
+fn item_name<'a>(item_registry: &'a ItemRegistry, id: &'a str) -> &'a str {
+    item_registry.get(id).map_or(id, |item| item.name.as_str())
+}
+
+/// Recalculate the stat modifiers when the equipment or the item definitions change
+pub(crate) fn update_stat_modifiers(
+    item_registry: Res<ItemRegistry>,
+    mut holders: Query<(&Equipment, ChangeTrackers<Equipment>, &mut StatModifiers)>,
+) {
+    holders.for_each_mut(|(equipment, tracker, mut modifiers)| {
+        if !tracker.is_changed() && !item_registry.is_changed() {
+            return;
+        }
+
+        let mut combined = StatModifiers::default();
+        for (_, id) in equipment.iter() {
+            let item = match item_registry.get(id) {
+                Some(item) => item,
+                None => continue,
+            };
+            for modifier in &item.modifiers {
+                match *modifier {
+                    StatModifier::Speed(amount) => combined.speed *= amount,
+                    StatModifier::Jump(amount) => combined.jump *= amount,
+                    StatModifier::Damage(amount) => combined.damage += amount,
+                    StatModifier::Special(special) => {
+                        if !combined.has(special) {
+                            combined.specials.push(special)
+                        }
+                    }
+                }
+            }
+        }
+        *modifiers = combined;
+    });
+}
+
+/// Show the secrets while any player has them revealed
+pub(crate) fn reveal_secrets(
+    players: Query<&StatModifiers, With<Player>>,
+    mut secrets: Query<&mut Visibility, With<Secret>>,
+) {
+    let revealed = players
+        .iter()
+        .any(|modifiers| modifiers.has(SpecialEffect::RevealSecrets));
+    secrets.for_each_mut(|mut visibility| {
+        visibility.is_visible = revealed;
+    });
+}
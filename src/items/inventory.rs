@@ -1,23 +1,63 @@
-use crate::prelude::{Player, Fonts};
-
-use super::ItemDescriptor;
+use super::{ItemDescriptor, ItemId, ItemRegistry};
 use bevy::prelude::*;
 
+/// A number of units of the same item in an inventory slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemStack {
+    // The id of the item in the [`ItemRegistry`](super::ItemRegistry)
+    pub id: ItemId,
+    // The number of units in the stack
+    pub count: u32,
+}
+
+/// What happened to an inventory slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InventoryChange {
+    // Units were added to the slot
+    Added { item: ItemId, count: u32 },
+    // Units were taken out of the slot
+    Removed { item: ItemId, count: u32 },
+    // The stack in the slot was moved to another slot
+    Moved { item: ItemId, count: u32, to: usize },
+}
+
+/// An event sent for every change to an inventory slot
+#[derive(Debug, Clone)]
+pub struct InventoryChanged {
+    // The entity that owns the inventory
+    pub entity: Entity,
+    // The slot that changed
+    pub slot: usize,
+    pub change: InventoryChange,
+}
+
+/// Why units couldn't be moved between inventories
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferError {
+    // There is no item in the slot
+    EmptySlot,
+    // The item isn't in the item registry
+    UnknownItem,
+    // The other inventory has no room for any of the units
+    InventoryFull,
+}
+
 /// Represents the inventory of an entity
 #[derive(Debug, Clone, Component)]
 pub struct Inventory {
     // The inventory's capacity
     pub capacity: usize,
-    /// The items in the inventory
-    pub items: Vec<ItemDescriptor>,
+    // The slots of the inventory, empty slots are `None`
+    slots: Vec<Option<ItemStack>>,
+    // The slot that is used by the use item button
+    pub selected: usize,
+    // The changes that haven't been sent as [`InventoryChanged`] events yet
+    changes: Vec<(usize, InventoryChange)>,
 }
 
 impl Default for Inventory {
     fn default() -> Self {
-        Self {
-            capacity: 10,
-            items: Vec::with_capacity(10.0 as usize),
-        }
+        Self::with_capacity(10)
     }
 }
 
@@ -26,74 +66,371 @@ impl Inventory {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             capacity,
-            items: Vec::with_capacity(capacity),
+            slots: vec![None; capacity],
+            selected: 0,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Add units of an item to the inventory
+    /// Fills the existing stacks of the item before using empty slots
+    /// Returns the number of units that didn't fit
+    pub fn add_item(&mut self, item: &ItemDescriptor, count: u32) -> u32 {
+        let max_stack = item.max_stack.max(1);
+        let mut remaining = count;
+
+        // Merge into the existing stacks
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if remaining == 0 {
+                break;
+            }
+            let stack = match slot {
+                Some(stack) if stack.id == item.id && stack.count < max_stack => stack,
+                _ => continue,
+            };
+            let added = remaining.min(max_stack - stack.count);
+            stack.count += added;
+            remaining -= added;
+            self.changes.push((
+                index,
+                InventoryChange::Added {
+                    item: item.id.clone(),
+                    count: added,
+                },
+            ));
+        }
+
+        // Start new stacks in the empty slots
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if remaining == 0 {
+                break;
+            }
+            if slot.is_some() {
+                continue;
+            }
+            let added = remaining.min(max_stack);
+            *slot = Some(ItemStack {
+                id: item.id.clone(),
+                count: added,
+            });
+            remaining -= added;
+            self.changes.push((
+                index,
+                InventoryChange::Added {
+                    item: item.id.clone(),
+                    count: added,
+                },
+            ));
         }
+
+        remaining
     }
 
-    /// Add an item to the inventory
-    pub fn add_item(&mut self, item: ItemDescriptor) -> Option<()> {
-        if self.items.len() < self.capacity {
-            self.items.push(item);
-            return Some(());
+    /// Remove units from the stack in a slot
+    /// Returns the removed units, which may be fewer than requested
+    pub fn remove_item(&mut self, index: usize, count: u32) -> Option<ItemStack> {
+        let slot = self.slots.get_mut(index)?;
+        let stack = slot.as_mut()?;
+        let removed = ItemStack {
+            id: stack.id.clone(),
+            count: count.min(stack.count),
+        };
+        stack.count -= removed.count;
+        if stack.count == 0 {
+            *slot = None;
+        }
+        if removed.count > 0 {
+            self.changes.push((
+                index,
+                InventoryChange::Removed {
+                    item: removed.id.clone(),
+                    count: removed.count,
+                },
+            ));
         }
-        None
+        Some(removed)
     }
 
-    /// Remove an item from the inventory
-    pub fn remove_item(&mut self, index: usize) -> Option<()> {
-        if index < self.items.len() {
-            self.items.remove(index);
-            return Some(());
+    /// Remove units of an item from the whole inventory, starting with the last stacks
+    /// Returns the number of units that were removed
+    pub fn remove_by_id(&mut self, id: &str, count: u32) -> u32 {
+        let mut removed = 0;
+        for index in (0..self.slots.len()).rev() {
+            if removed == count {
+                break;
+            }
+            if self.get_item(index).map_or(false, |stack| stack.id == id) {
+                removed += self
+                    .remove_item(index, count - removed)
+                    .map_or(0, |stack| stack.count);
+            }
         }
-        None
+        removed
+    }
+
+    /// Get the stack in a slot
+    pub fn get_item(&self, index: usize) -> Option<&ItemStack> {
+        self.slots.get(index).and_then(|slot| slot.as_ref())
+    }
+
+    /// Count how many units of an item still fit in the inventory
+    pub fn room_for(&self, item: &ItemDescriptor) -> u32 {
+        let max_stack = item.max_stack.max(1);
+        self.slots
+            .iter()
+            .map(|slot| match slot {
+                Some(stack) if stack.id == item.id => max_stack.saturating_sub(stack.count),
+                Some(_) => 0,
+                None => max_stack,
+            })
+            .sum()
     }
 
-    /// Get an item from the inventory
-    pub fn get_item(&self, index: usize) -> Option<&ItemDescriptor> {
-        self.items.get(index)
+    /// Count the units of an item in the whole inventory
+    pub fn count(&self, id: &str) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|stack| stack.id == id)
+            .map(|stack| stack.count)
+            .sum()
     }
 
-    /// Insert an item into the inventory
-    pub fn insert_item(&mut self, index: usize, item: ItemDescriptor) -> Option<()> {
-        if index < self.items.len() {
-            self.items.insert(index, item);
-            return Some(());
+    /// Select the next filled slot, wrapping around at the end
+    pub fn select_next(&mut self) {
+        let count = self.slots.len();
+        if let Some(next) = (1..=count)
+            .map(|offset| (self.selected + offset) % count)
+            .find(|index| self.slots[*index].is_some())
+        {
+            self.selected = next;
         }
-        None
     }
+
+    /// Merge the stacks and order them by category and name, leaving the empty slots at the end
+    /// The stacks that end up in another slot are reported as moved, the rest as removed and added
+    /// Returns the stacks that no longer fit, like when a max stack shrank, so they can be dropped
+    #[must_use]
+    pub fn sort(&mut self, item_registry: &ItemRegistry) -> Vec<ItemStack> {
+        let before = self.slots.clone();
+        let recorded = self.changes.len();
+        let mut stacks = self.slots.iter_mut().filter_map(Option::take).collect::<Vec<_>>();
+        stacks.sort_by(|a, b| {
+            let key = |stack: &ItemStack| {
+                item_registry
+                    .get(&stack.id)
+                    .map(|item| (item.category, item.name.clone()))
+            };
+            key(a).cmp(&key(b)).then_with(|| a.id.cmp(&b.id))
+        });
+
+        let mut overflow = vec![];
+        for stack in stacks {
+            match item_registry.get(&stack.id) {
+                Some(item) => {
+                    let left = self.add_item(item, stack.count);
+                    if left > 0 {
+                        overflow.push(ItemStack {
+                            id: stack.id,
+                            count: left,
+                        });
+                    }
+                }
+                // Keep the stacks of unknown items as they are
+                None => match self.slots.iter_mut().find(|slot| slot.is_none()) {
+                    Some(slot) => *slot = Some(stack),
+                    None => overflow.push(stack),
+                },
+            }
+        }
+        self.selected = 0;
+
+        // Describe the sort as a whole instead of the steps it took
+        self.changes.truncate(recorded);
+        self.record_rearrangement(&before);
+        overflow
+    }
+
+    /// Record the changes between the slots before a rearrangement and the current slots
+    fn record_rearrangement(&mut self, before: &[Option<ItemStack>]) {
+        let changed = |index: usize| before[index] != self.slots[index];
+        let mut unmatched = (0..self.slots.len())
+            .filter(|index| changed(*index) && self.slots[*index].is_some())
+            .collect::<Vec<_>>();
+        let mut moved = Vec::new();
+        let mut removed = Vec::new();
+
+        for (index, old) in before.iter().enumerate() {
+            let old = match old {
+                Some(old) if changed(index) => old,
+                _ => continue,
+            };
+            // Identical stacks that show up in another slot were moved there
+            match unmatched
+                .iter()
+                .position(|to| self.slots[*to].as_ref() == Some(old))
+            {
+                Some(position) => {
+                    let to = unmatched.remove(position);
+                    moved.push((
+                        index,
+                        InventoryChange::Moved {
+                            item: old.id.clone(),
+                            count: old.count,
+                            to,
+                        },
+                    ));
+                }
+                None => removed.push((
+                    index,
+                    InventoryChange::Removed {
+                        item: old.id.clone(),
+                        count: old.count,
+                    },
+                )),
+            }
+        }
+
+        // The stacks left over are new
+        let added = unmatched
+            .into_iter()
+            .filter_map(|index| {
+                let stack = self.slots[index].as_ref()?;
+                Some((
+                    index,
+                    InventoryChange::Added {
+                        item: stack.id.clone(),
+                        count: stack.count,
+                    },
+                ))
+            })
+            .collect::<Vec<_>>();
+        self.changes.extend(removed);
+        self.changes.extend(moved);
+        self.changes.extend(added);
+    }
+
+    /// Iterate over the filled slots with their indices
+    pub fn stacks(&self) -> impl Iterator<Item = (usize, &ItemStack)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| slot.as_ref().map(|stack| (index, stack)))
+    }
+}
+
+/// Move units of the stack in a slot of one inventory into another inventory
+/// The units fill the matching stacks first, the units that don't fit stay in the slot
+/// Returns the number of units that were moved
+pub fn transfer(
+    from: &mut Inventory,
+    slot: usize,
+    to: &mut Inventory,
+    count: u32,
+    item_registry: &ItemRegistry,
+) -> Result<u32, TransferError> {
+    let stack = from.get_item(slot).ok_or(TransferError::EmptySlot)?;
+    let item = item_registry.get(&stack.id).ok_or(TransferError::UnknownItem)?;
+
+    // Only take the units that fit, so the slot keeps the rest
+    let moved = count.min(stack.count).min(to.room_for(item));
+    if moved == 0 {
+        return Err(TransferError::InventoryFull);
+    }
+    from.remove_item(slot, moved);
+    to.add_item(item, moved);
+    Ok(moved)
 }
 
-/// Spawns the inentory menu of the player
-pub fn spawn_inventory_menu(
-    fonts: Res<Fonts>,
-    inventory: Query<&Inventory, With<Player>>, 
-    mut commands: Commands,
+/// Send the recorded inventory changes as [`InventoryChanged`] events
+pub(crate) fn send_inventory_changes(
+    mut inventories: Query<(Entity, &mut Inventory)>,
+    mut changed_events: EventWriter<InventoryChanged>,
 ) {
-    // Only show the players inventory
-    if let Some(inventory) = inventory.iter().next() {
-        commands.spawn_bundle(NodeBundle {
-            style : Style {
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                margin: Rect::all(Val::Px(300.0)),
-                ..Default::default()
-            },
-            color: Color::BLACK.into(),
-            ..Default::default()
-        }).with_children(|parent| {
-            // Spawn the title text
-            parent.spawn_bundle(TextBundle {
-                text: Text::with_section(
-                    "Inventory",
-                    TextStyle {
-                        font: fonts.fira_sans.clone(),
-                        font_size: 100.0,
-                        ..Default::default()
-                    },
-                    Default::default(),
-                ),
-                ..Default::default()
+    for (entity, mut inventory) in inventories.iter_mut() {
+        if inventory.changes.is_empty() {
+            continue;
+        }
+        for (slot, change) in inventory.changes.drain(..) {
+            changed_events.send(InventoryChanged {
+                entity,
+                slot,
+                change,
             });
-        });
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::{test_item, ItemCategory};
+
+    #[test]
+    fn add_item_fills_stacks_and_returns_the_overflow() {
+        let bean = test_item("bean", ItemCategory::Food, 5);
+        let mut inventory = Inventory::with_capacity(2);
+
+        assert_eq!(inventory.add_item(&bean, 3), 0);
+        // The first stack is filled before the second one is started
+        assert_eq!(inventory.add_item(&bean, 9), 2);
+        assert_eq!(inventory.get_item(0).map(|stack| stack.count), Some(5));
+        assert_eq!(inventory.get_item(1).map(|stack| stack.count), Some(5));
+        assert_eq!(inventory.add_item(&bean, 1), 1);
+    }
+
+    #[test]
+    fn transfer_moves_only_the_units_that_fit() {
+        let bean = test_item("bean", ItemCategory::Food, 10);
+        let mut item_registry = ItemRegistry::default();
+        item_registry.insert(bean.id.clone(), bean.clone());
+
+        let mut from = Inventory::with_capacity(1);
+        from.add_item(&bean, 8);
+        let mut to = Inventory::with_capacity(1);
+        to.add_item(&bean, 7);
+
+        assert_eq!(transfer(&mut from, 0, &mut to, 8, &item_registry), Ok(3));
+        assert_eq!(from.count("bean"), 5);
+        assert_eq!(to.count("bean"), 10);
+
+        // A full inventory takes nothing and the slot keeps its units
+        assert_eq!(
+            transfer(&mut from, 0, &mut to, 5, &item_registry),
+            Err(TransferError::InventoryFull)
+        );
+        assert_eq!(from.count("bean"), 5);
+        assert_eq!(
+            transfer(&mut from, 1, &mut to, 1, &item_registry),
+            Err(TransferError::EmptySlot)
+        );
+    }
+
+    #[test]
+    fn sort_orders_the_stacks_and_returns_the_ones_that_no_longer_fit() {
+        let bean = test_item("bean", ItemCategory::Food, 10);
+        let coin = test_item("coin", ItemCategory::Misc, 10);
+        let mut item_registry = ItemRegistry::default();
+        item_registry.insert(bean.id.clone(), bean.clone());
+        item_registry.insert(coin.id.clone(), coin.clone());
+
+        let mut inventory = Inventory::with_capacity(3);
+        inventory.add_item(&bean, 8);
+        inventory.add_item(&coin, 4);
+
+        // The bean stacks shrank below what the inventory holds
+        item_registry.insert(bean.id.clone(), test_item("bean", ItemCategory::Food, 3));
+        let overflow = inventory.sort(&item_registry);
+
+        assert_eq!(inventory.get_item(0).map(|stack| stack.id.as_str()), Some("coin"));
+        assert_eq!(inventory.get_item(1).map(|stack| stack.count), Some(3));
+        assert_eq!(inventory.get_item(2).map(|stack| stack.count), Some(3));
+        assert_eq!(
+            overflow,
+            vec![ItemStack {
+                id: ItemId::new("bean"),
+                count: 2,
+            }]
+        );
+    }
+}
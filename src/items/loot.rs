@@ -0,0 +1,466 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use hashbrown::HashMap;
+use serde::Deserialize;
+use std::ops::{Deref, DerefMut};
+
+use super::{
+    data::{merge_data_files, DataFile, DataFileLoader, DataHandles, HasId},
+    equipment::Equipment,
+    inventory::{Inventory, ItemStack},
+    throw::Thrown,
+    ItemId, ItemRegistry, SpawnItemEvent,
+};
+use crate::{
+    health::DeathEvent,
+    levels::{Chest, ChestOpenedEvent, Lock},
+    physics::component::CollidingEntities,
+    player::component::{Player, PlayerState},
+    prelude::AssetLoadingState,
+    rng::GameRng,
+};
+
+/// The folder the loot table files are loaded from
+pub const LOOT_TABLE_FOLDER: &str = "loot";
+
+/// A condition that has to hold for a loot entry to drop
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum LootCondition {
+    // The looter has the item in its inventory or equipment
+    Has(ItemId),
+    // The looter doesn't have the item in its inventory or equipment
+    Lacks(ItemId),
+}
+
+/// An item that can drop from a loot table
+#[derive(Debug, Clone, Deserialize)]
+pub struct LootEntry {
+    pub item: ItemId,
+    // The chance of the entry compared to the other entries
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    // The minimum and maximum number of units that drop, both inclusive
+    #[serde(default = "default_range")]
+    pub count: (u32, u32),
+    #[serde(default)]
+    pub conditions: Vec<LootCondition>,
+}
+
+/// A table of the items that drop from a pot, chest or enemy
+#[derive(Debug, Clone, Deserialize)]
+pub struct LootTable {
+    pub id: String,
+    // The minimum and maximum number of weighted rolls, both inclusive
+    #[serde(default = "default_range")]
+    pub rolls: (u32, u32),
+    // The chance of a roll dropping nothing, compared to the entries
+    #[serde(default)]
+    pub nothing: u32,
+    // The entries that always drop once their conditions hold
+    #[serde(default)]
+    pub guaranteed: Vec<LootEntry>,
+    // The entries the rolls pick from
+    #[serde(default)]
+    pub entries: Vec<LootEntry>,
+}
+
+/// Entries are equally likely unless their weight says otherwise
+fn default_weight() -> u32 {
+    1
+}
+
+/// Tables roll once and entries drop one unit unless they say otherwise
+fn default_range() -> (u32, u32) {
+    (1, 1)
+}
+
+impl LootTable {
+    /// Roll the table, the entries only drop if `allowed` accepts all their conditions
+    pub fn roll(
+        &self,
+        rng: &mut GameRng,
+        allowed: impl Fn(&LootCondition) -> bool,
+    ) -> Vec<ItemStack> {
+        let available = |entry: &&LootEntry| entry.conditions.iter().all(&allowed);
+        let mut drops = vec![];
+
+        for entry in self.guaranteed.iter().filter(available) {
+            drops.push(ItemStack {
+                id: entry.item.clone(),
+                count: rng.range_u32(entry.count.0, entry.count.1),
+            });
+        }
+
+        let entries = self.entries.iter().filter(available).collect::<Vec<_>>();
+        let total = self.nothing + entries.iter().map(|entry| entry.weight).sum::<u32>();
+        if total == 0 {
+            return drops;
+        }
+        for _ in 0..rng.range_u32(self.rolls.0, self.rolls.1) {
+            let mut pick = rng.range_u32(0, total - 1);
+            for entry in &entries {
+                if pick < entry.weight {
+                    drops.push(ItemStack {
+                        id: entry.item.clone(),
+                        count: rng.range_u32(entry.count.0, entry.count.1),
+                    });
+                    break;
+                }
+                pick -= entry.weight;
+            }
+            // Picks past the last entry drop nothing
+        }
+        drops.retain(|stack| stack.count > 0);
+        drops
+    }
+}
+
+/// A file of loot tables
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "c3e1f0a4-2d6b-4f87-9a51-7e0d2b8c4f16"]
+pub struct LootTableFile {
+    pub tables: Vec<LootTable>,
+    // The path of the file the tables were loaded from
+    #[serde(skip)]
+    pub path: String,
+}
+
+impl HasId for LootTable {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl DataFile for LootTableFile {
+    type Entry = LootTable;
+
+    const KIND: &'static str = "loot table";
+    const FOLDER: &'static str = LOOT_TABLE_FOLDER;
+    const EXTENSIONS: &'static [&'static str] = &["loot.ron"];
+
+    fn entries(&self) -> &[LootTable] {
+        &self.tables
+    }
+
+    fn entries_mut(&mut self) -> &mut Vec<LootTable> {
+        &mut self.tables
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn set_path(&mut self, path: String) {
+        self.path = path;
+    }
+
+    fn loaded_flag(state: &mut AssetLoadingState) -> &mut bool {
+        &mut state.loot_loaded
+    }
+
+    fn validate(table: &LootTable) -> Result<(), String> {
+        if table.rolls.0 > table.rolls.1 {
+            return Err(format!(
+                "it rolls at least {} but at most {} times",
+                table.rolls.0, table.rolls.1
+            ));
+        }
+        for entry in table.guaranteed.iter().chain(&table.entries) {
+            if entry.count.0 > entry.count.1 {
+                return Err(format!(
+                    "it drops at least {} but at most {} of \"{}\"",
+                    entry.count.0, entry.count.1, entry.item
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads `.loot.ron` files into [`LootTableFile`]s
+pub type LootTableLoader = DataFileLoader<LootTableFile>;
+
+/// The handles of the loaded loot table files
+pub type LootTableHandles = DataHandles<LootTableFile>;
+
+/// The loot tables by their id
+#[derive(Debug, Default, Clone)]
+pub struct LootTables(pub HashMap<String, LootTable>);
+
+impl Deref for LootTables {
+    type Target = HashMap<String, LootTable>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for LootTables {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// The config for spawning loot
+#[derive(Debug, Clone)]
+pub struct LootConfig {
+    // The highest horizontal speed the drops scatter with
+    pub scatter: f32,
+    // The lowest and highest upwards speed the drops pop out with
+    pub lift: (f32, f32),
+}
+
+impl Default for LootConfig {
+    fn default() -> Self {
+        Self {
+            scatter: 60.0,
+            lift: (80.0, 140.0),
+        }
+    }
+}
+
+/// The loot table an entity drops from when it is broken, opened or defeated
+#[derive(Debug, Clone, Component)]
+pub struct LootDrop(pub String);
+
+/// An event that rolls a loot table and spawns the drops
+#[derive(Debug, Clone)]
+pub struct DropLootEvent {
+    pub table: String,
+    pub position: Vec2,
+    // The entity the conditions are checked against, all players when none
+    pub looter: Option<Entity>,
+}
+
+/// Rebuild the loot tables whenever a loot table file or the item registry changes
+/// The tables that drop unknown items are skipped
+pub(crate) fn rebuild_loot_tables(
+    files: Res<Assets<LootTableFile>>,
+    item_registry: Res<ItemRegistry>,
+    mut file_events: EventReader<AssetEvent<LootTableFile>>,
+    mut loot_tables: ResMut<LootTables>,
+) {
+    if file_events.iter().count() == 0 && !item_registry.is_changed() {
+        return;
+    }
+
+    loot_tables.clear();
+    for (path, table) in merge_data_files(&files) {
+        let unknown = table
+            .guaranteed
+            .iter()
+            .chain(&table.entries)
+            .flat_map(|entry| {
+                let conditions = entry.conditions.iter().map(|condition| match condition {
+                    LootCondition::Has(item) | LootCondition::Lacks(item) => item,
+                });
+                std::iter::once(&entry.item).chain(conditions)
+            })
+            .find(|item| !item_registry.contains_key(*item));
+        if let Some(item) = unknown {
+            error!(
+                "{}: skipping the loot table \"{}\": the item \"{}\" doesn't exist",
+                path, table.id, item
+            );
+            continue;
+        }
+        loot_tables.insert(table.id.clone(), table.clone());
+    }
+    info!("Registered {} loot tables", loot_tables.len());
+}
+
+/// Drop the loot of the entities that died
+/// The conditions are checked against the thrower or the entity that dealt the blow
+pub(crate) fn drop_loot_on_death(
+    droppers: Query<(&LootDrop, &Transform)>,
+    thrown: Query<&Thrown>,
+    holders: Query<(), With<Inventory>>,
+    mut death_events: EventReader<DeathEvent>,
+    mut loot_events: EventWriter<DropLootEvent>,
+) {
+    for event in death_events.iter() {
+        let (loot, transform) = match droppers.get(event.entity) {
+            Ok(dropper) => dropper,
+            Err(_) => continue,
+        };
+        let looter = event.source.and_then(|source| match thrown.get(source) {
+            Ok(thrown) => Some(thrown.by),
+            Err(_) => holders.get(source).ok().map(|_| source),
+        });
+        loot_events.send(DropLootEvent {
+            table: loot.0.clone(),
+            position: transform.translation.truncate(),
+            looter,
+        });
+    }
+}
+
+/// Open the chests the players touch and press the interact button at
+pub(crate) fn open_chests(
+    players: Query<(Entity, &PlayerState, &CollidingEntities), With<Player>>,
+    mut chests: Query<(&mut Chest, &Transform, Option<&LootDrop>, Option<&Lock>)>,
+    mut opened_events: EventWriter<ChestOpenedEvent>,
+    mut loot_events: EventWriter<DropLootEvent>,
+) {
+    players.for_each(|(player, state, colliding_entities)| {
+        if !state.interact_pressed {
+            return;
+        }
+        for entity in colliding_entities.iter() {
+            let (mut chest, transform, loot, lock) = match chests.get_mut(*entity) {
+                Ok(chest) => chest,
+                Err(_) => continue,
+            };
+            // Locked chests have to be unlocked first
+            if chest.opened || lock.map_or(false, |lock| !lock.is_open()) {
+                continue;
+            }
+            chest.opened = true;
+            opened_events.send(ChestOpenedEvent {
+                chest: *entity,
+                opened_by: player,
+            });
+            if let Some(loot) = loot {
+                loot_events.send(DropLootEvent {
+                    table: loot.0.clone(),
+                    position: transform.translation.truncate(),
+                    looter: Some(player),
+                });
+            }
+        }
+    });
+}
+
+/// Roll the loot tables and scatter the drops around where they dropped
+pub(crate) fn roll_loot(
+    loot_tables: Res<LootTables>,
+    item_registry: Res<ItemRegistry>,
+    config: Res<LootConfig>,
+    mut rng: ResMut<GameRng>,
+    holders: Query<(&Inventory, Option<&Equipment>)>,
+    players: Query<Entity, With<Player>>,
+    mut loot_events: EventReader<DropLootEvent>,
+    mut spawn_item_events: EventWriter<SpawnItemEvent>,
+) {
+    for event in loot_events.iter() {
+        let table = match loot_tables.get(&event.table) {
+            Some(table) => table,
+            None => {
+                error!("Loot table not found: {}", event.table);
+                continue;
+            }
+        };
+
+        // Check if an entity has the item in its inventory or equipment
+        let holds = |entity: Entity, item: &ItemId| {
+            holders.get(entity).map_or(false, |(inventory, equipment)| {
+                inventory.count(item) > 0
+                    || equipment.map_or(false, |equipment| {
+                        equipment.iter().any(|(_, id)| id == item)
+                    })
+            })
+        };
+        let looters = match event.looter {
+            Some(looter) => vec![looter],
+            None => players.iter().collect(),
+        };
+        let drops = table.roll(&mut rng, |condition| match condition {
+            LootCondition::Has(item) => looters.iter().any(|looter| holds(*looter, item)),
+            LootCondition::Lacks(item) => !looters.iter().any(|looter| holds(*looter, item)),
+        });
+
+        for stack in drops {
+            if !item_registry.contains_key(&stack.id) {
+                error!(
+                    "The loot table \"{}\" drops the unknown item \"{}\"",
+                    table.id, stack.id
+                );
+                continue;
+            }
+            for _ in 0..stack.count {
+                let velocity = Vec2::new(
+                    rng.range_f32(-config.scatter, config.scatter),
+                    rng.range_f32(config.lift.0, config.lift.1),
+                );
+                spawn_item_events.send(SpawnItemEvent {
+                    item: stack.id.clone(),
+                    position: event.position,
+                    velocity,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(item: &str, weight: u32, count: (u32, u32)) -> LootEntry {
+        LootEntry {
+            item: ItemId::new(item),
+            weight,
+            count,
+            conditions: vec![],
+        }
+    }
+
+    fn chest() -> LootTable {
+        LootTable {
+            id: "chest".into(),
+            rolls: (2, 4),
+            nothing: 1,
+            guaranteed: vec![LootEntry {
+                conditions: vec![LootCondition::Lacks(ItemId::new("stick"))],
+                ..entry("stick", 1, (1, 1))
+            }],
+            entries: vec![entry("bean", 3, (1, 3)), entry("coin", 2, (2, 5))],
+        }
+    }
+
+    #[test]
+    fn roll_is_the_same_for_the_same_seed() {
+        let table = chest();
+        for seed in 0..20 {
+            let first = table.roll(&mut GameRng::new(seed), |_| true);
+            let second = table.roll(&mut GameRng::new(seed), |_| true);
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn roll_stays_within_the_table() {
+        let table = chest();
+        for seed in 0..100 {
+            let drops = table.roll(&mut GameRng::new(seed), |_| true);
+            // The guaranteed stick comes first, followed by at most one stack per roll
+            assert_eq!(drops[0].id, ItemId::new("stick"));
+            assert!(drops.len() <= 5);
+            for stack in &drops[1..] {
+                match stack.id.as_str() {
+                    "bean" => assert!((1..=3).contains(&stack.count)),
+                    "coin" => assert!((2..=5).contains(&stack.count)),
+                    other => panic!("unexpected drop {}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn roll_skips_the_entries_whose_conditions_fail() {
+        let table = LootTable {
+            nothing: 0,
+            rolls: (3, 3),
+            ..chest()
+        };
+        // The looter already has a stick
+        let allowed = |condition: &LootCondition| {
+            !matches!(condition, LootCondition::Lacks(item) if item == "stick")
+        };
+        let drops = table.roll(&mut GameRng::new(7), allowed);
+
+        // Without a chance of nothing, every roll drops something
+        assert_eq!(drops.len(), 3);
+        assert!(drops.iter().all(|stack| stack.id != "stick"));
+    }
+}
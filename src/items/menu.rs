@@ -0,0 +1,675 @@
+use bevy::{ecs::system::SystemParam, input::gamepad::Gamepads, prelude::*};
+use std::marker::PhantomData;
+
+use super::{
+    crafting::{CraftItemEvent, CraftedEvent, Recipes},
+    effect::{ItemMessage, ItemUsedEvent, UseItemEvent},
+    equipment::{EquipItemEvent, EquipSlot, Equipment, UnequipItemEvent},
+    inventory::Inventory,
+    throw::{DropItemEvent, ThrowConfig},
+    ItemCategory, ItemRegistry, SpawnItemEvent,
+};
+use crate::{
+    assets::Fonts,
+    player::component::{ControllerState, PlayerState},
+    replay::ReplayMode,
+    simulation::SimulationTime,
+    state::GameState,
+};
+
+/// The number of slots in a row of the inventory grid
+const GRID_COLUMNS: usize = 5;
+
+/// The tabs of the inventory menu, `None` shows every item
+const TABS: [Option<ItemCategory>; 6] = [
+    None,
+    Some(ItemCategory::Misc),
+    Some(ItemCategory::Food),
+    Some(ItemCategory::PowerUp),
+    Some(ItemCategory::Weapon),
+    Some(ItemCategory::Key),
+];
+
+/// The tab after the item tabs, it lists the recipes that can be crafted
+const CRAFTING_TAB: usize = TABS.len();
+
+/// The number of tabs, including the crafting tab
+const TAB_COUNT: usize = TABS.len() + 1;
+
+/// The marker component for the ui of the inventory menu
+#[derive(Debug, Default, Clone, Component)]
+pub struct InventoryMenuUi;
+
+/// The state of the inventory menu
+#[derive(Debug, Default, Clone)]
+pub struct InventoryMenuState {
+    // The player whose inventory is shown
+    pub owner: Option<Entity>,
+    // The index of the selected tab
+    pub tab: usize,
+    // The index of the selected slot or recipe among the ones shown in the tab
+    pub cursor: usize,
+    // Whether the ui has to be rebuilt
+    pub dirty: bool,
+}
+
+/// An action the player can take in the inventory menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuAction {
+    Move(i32, i32),
+    PreviousTab,
+    NextTab,
+    Use,
+    Equip,
+    Unequip,
+    Drop,
+    Throw,
+    Sort,
+    Close,
+}
+
+/// Get the inventory slots that are shown in a tab, the crafting tab shows none
+fn visible_slots(inventory: &Inventory, item_registry: &ItemRegistry, tab: usize) -> Vec<usize> {
+    let tab = match TABS.get(tab) {
+        Some(tab) => *tab,
+        None => return vec![],
+    };
+    inventory
+        .stacks()
+        .filter(|(_, stack)| {
+            tab.map_or(true, |category| {
+                item_registry
+                    .get(&stack.id)
+                    .map_or(false, |item| item.category == category)
+            })
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Opens the inventory menu for the player that pressed the menu button
+pub(crate) fn open_inventory_menu(mut menu: ResMut<InventoryMenuState>) {
+    *menu = InventoryMenuState {
+        owner: menu.owner,
+        dirty: true,
+        ..Default::default()
+    };
+}
+
+/// Removes the ui of the inventory menu
+pub(crate) fn close_inventory_menu(
+    mut commands: Commands,
+    mut menu: ResMut<InventoryMenuState>,
+    ui: Query<Entity, With<InventoryMenuUi>>,
+) {
+    ui.for_each(|entity| commands.entity(entity).despawn_recursive());
+    menu.owner = None;
+}
+
+/// Every action of the inventory menu, the one that closes it first
+const ACTIONS: [MenuAction; 13] = [
+    MenuAction::Close,
+    MenuAction::Move(0, -1),
+    MenuAction::Move(0, 1),
+    MenuAction::Move(-1, 0),
+    MenuAction::Move(1, 0),
+    MenuAction::PreviousTab,
+    MenuAction::NextTab,
+    MenuAction::Use,
+    MenuAction::Equip,
+    MenuAction::Unequip,
+    MenuAction::Drop,
+    MenuAction::Throw,
+    MenuAction::Sort,
+];
+
+/// The keys of the inventory menu actions
+const KEYS: [(KeyCode, MenuAction); 19] = [
+    (KeyCode::W, MenuAction::Move(0, -1)),
+    (KeyCode::Up, MenuAction::Move(0, -1)),
+    (KeyCode::S, MenuAction::Move(0, 1)),
+    (KeyCode::Down, MenuAction::Move(0, 1)),
+    (KeyCode::A, MenuAction::Move(-1, 0)),
+    (KeyCode::Left, MenuAction::Move(-1, 0)),
+    (KeyCode::D, MenuAction::Move(1, 0)),
+    (KeyCode::Right, MenuAction::Move(1, 0)),
+    (KeyCode::Z, MenuAction::PreviousTab),
+    (KeyCode::C, MenuAction::NextTab),
+    (KeyCode::Return, MenuAction::Use),
+    (KeyCode::Space, MenuAction::Use),
+    (KeyCode::E, MenuAction::Equip),
+    (KeyCode::X, MenuAction::Unequip),
+    (KeyCode::G, MenuAction::Drop),
+    (KeyCode::T, MenuAction::Throw),
+    (KeyCode::R, MenuAction::Sort),
+    (KeyCode::Escape, MenuAction::Close),
+    (KeyCode::Q, MenuAction::Close),
+];
+
+/// The gamepad buttons of the inventory menu actions
+const BUTTONS: [(GamepadButtonType, MenuAction); 14] = [
+    (GamepadButtonType::DPadUp, MenuAction::Move(0, -1)),
+    (GamepadButtonType::DPadDown, MenuAction::Move(0, 1)),
+    (GamepadButtonType::DPadLeft, MenuAction::Move(-1, 0)),
+    (GamepadButtonType::DPadRight, MenuAction::Move(1, 0)),
+    (GamepadButtonType::LeftTrigger, MenuAction::PreviousTab),
+    (GamepadButtonType::RightTrigger, MenuAction::NextTab),
+    (GamepadButtonType::South, MenuAction::Use),
+    (GamepadButtonType::West, MenuAction::Equip),
+    (GamepadButtonType::LeftThumb, MenuAction::Unequip),
+    (GamepadButtonType::North, MenuAction::Drop),
+    (GamepadButtonType::RightTrigger2, MenuAction::Throw),
+    (GamepadButtonType::RightThumb, MenuAction::Sort),
+    (GamepadButtonType::East, MenuAction::Close),
+    (GamepadButtonType::Select, MenuAction::Close),
+];
+
+/// The input of the menus
+/// The menus change the inventories, so their actions are recorded and played back with the runs
+#[derive(SystemParam)]
+pub(crate) struct MenuInput<'w, 's> {
+    keyboard_input: ResMut<'w, Input<KeyCode>>,
+    gamepad_button_input: ResMut<'w, Input<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>,
+    replay_mode: ResMut<'w, ReplayMode>,
+    simulation_time: Res<'w, SimulationTime>,
+    players: Query<'w, 's, &'static PlayerState>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> MenuInput<'w, 's> {
+    /// Read the actions a menu takes this frame from the device of its player, or from the replay
+    /// that is played back
+    /// `actions` lists every action of the menu, starting with the one that closes it, since the
+    /// replays store the actions by their index
+    pub fn read<A: Copy + PartialEq>(
+        &mut self,
+        player: Option<Entity>,
+        actions: &[A],
+        keys: &[(KeyCode, A)],
+        buttons: &[(GamepadButtonType, A)],
+    ) -> Vec<A> {
+        let tick = self.simulation_time.tick;
+        if let Some(recorded) = self.replay_mode.playback_menu_actions(tick) {
+            return recorded
+                .iter()
+                .filter_map(|index| actions.get(*index as usize).copied())
+                .collect();
+        }
+
+        // A menu without a player can be used from every device, so it can still be closed
+        let controller = player
+            .and_then(|player| self.players.get(player).ok())
+            .map(|state| state.input.controller);
+        let is_used = |device| controller.map_or(true, |controller| controller == device);
+        let mut read = vec![];
+        if is_used(ControllerState::Keyboard) {
+            for (key, action) in keys {
+                if self.keyboard_input.just_pressed(*key) {
+                    read.push(*action);
+                }
+            }
+        }
+        for gamepad in self.gamepads.iter() {
+            if !is_used(ControllerState::Gamepad(gamepad.0)) {
+                continue;
+            }
+            for (button, action) in buttons {
+                if self
+                    .gamepad_button_input
+                    .just_pressed(GamepadButton(*gamepad, *button))
+                {
+                    read.push(*action);
+                }
+            }
+        }
+
+        let indices = read
+            .iter()
+            .filter_map(|action| actions.iter().position(|other| other == action))
+            .map(|index| index as u8)
+            .collect();
+        self.replay_mode.record_menu_actions(tick, indices);
+        read
+    }
+
+    /// Swallow the keys and buttons that close menus, so they don't pause the game or open a
+    /// menu again on the next frame
+    fn swallow_close(&mut self) {
+        self.keyboard_input.reset(KeyCode::Escape);
+        self.keyboard_input.reset(KeyCode::Q);
+        for gamepad in self.gamepads.iter() {
+            let gamepad = *gamepad;
+            self.gamepad_button_input
+                .reset(GamepadButton(gamepad, GamepadButtonType::Select));
+            self.gamepad_button_input
+                .reset(GamepadButton(gamepad, GamepadButtonType::East));
+        }
+    }
+}
+
+/// Close a menu and go back to the gameplay
+pub(crate) fn close_menu(input: &mut MenuInput, gamestate: &mut State<GameState>) {
+    input.swallow_close();
+    if *gamestate.current() != GameState::Gameplay {
+        gamestate.set(GameState::Gameplay).unwrap();
+    }
+}
+
+/// Handle the input of the inventory menu
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_inventory_menu_input(
+    mut input: MenuInput,
+    item_registry: Res<ItemRegistry>,
+    recipes: Res<Recipes>,
+    mut menu: ResMut<InventoryMenuState>,
+    mut gamestate: ResMut<State<GameState>>,
+    throw_config: Res<ThrowConfig>,
+    mut owners: Query<(&mut Inventory, &Equipment, &Transform)>,
+    mut use_events: EventWriter<UseItemEvent>,
+    mut craft_events: EventWriter<CraftItemEvent>,
+    mut equip_events: EventWriter<EquipItemEvent>,
+    mut unequip_events: EventWriter<UnequipItemEvent>,
+    mut drop_events: EventWriter<DropItemEvent>,
+    mut spawn_item_events: EventWriter<SpawnItemEvent>,
+) {
+    let actions = input.read(menu.owner, &ACTIONS, &KEYS, &BUTTONS);
+    if actions.is_empty() {
+        return;
+    }
+
+    let owner = match menu.owner {
+        Some(owner) => owner,
+        None => {
+            if *gamestate.current() != GameState::Gameplay {
+                gamestate.set(GameState::Gameplay).unwrap();
+            }
+            return;
+        }
+    };
+    let (mut inventory, equipment, transform) = match owners.get_mut(owner) {
+        Ok(owner) => owner,
+        Err(_) => return,
+    };
+
+    for action in actions {
+        let slots = visible_slots(&inventory, &item_registry, menu.tab);
+        let selected = slots.get(menu.cursor).copied();
+        menu.dirty = true;
+
+        // The crafting tab is a list of recipes, it only moves and crafts
+        if menu.tab == CRAFTING_TAB {
+            let craftable = recipes.craftable(&inventory).collect::<Vec<_>>();
+            match action {
+                MenuAction::Move(_, y) => {
+                    let moved = menu.cursor as i32 + y;
+                    menu.cursor = moved.clamp(0, craftable.len().max(1) as i32 - 1) as usize;
+                    continue;
+                }
+                MenuAction::Use => {
+                    if let Some(recipe) = craftable.get(menu.cursor) {
+                        craft_events.send(CraftItemEvent {
+                            entity: owner,
+                            recipe: recipe.id.clone(),
+                        });
+                    }
+                    continue;
+                }
+                MenuAction::PreviousTab | MenuAction::NextTab | MenuAction::Close => {}
+                _ => continue,
+            }
+        }
+
+        match action {
+            MenuAction::Move(x, y) => {
+                if slots.is_empty() {
+                    continue;
+                }
+                let moved = menu.cursor as i32 + x + y * GRID_COLUMNS as i32;
+                menu.cursor = moved.clamp(0, slots.len() as i32 - 1) as usize;
+            }
+            MenuAction::PreviousTab => {
+                menu.tab = (menu.tab + TAB_COUNT - 1) % TAB_COUNT;
+                menu.cursor = 0;
+            }
+            MenuAction::NextTab => {
+                menu.tab = (menu.tab + 1) % TAB_COUNT;
+                menu.cursor = 0;
+            }
+            MenuAction::Use => {
+                if let Some(slot) = selected {
+                    use_events.send(UseItemEvent { user: owner, slot });
+                }
+            }
+            MenuAction::Equip => {
+                if let Some(slot) = selected {
+                    equip_events.send(EquipItemEvent {
+                        entity: owner,
+                        slot,
+                    });
+                }
+            }
+            MenuAction::Unequip => {
+                // Take off the item in the slot of the selected item, or the first equipped item
+                let slot = selected
+                    .and_then(|slot| inventory.get_item(slot))
+                    .and_then(|stack| item_registry.get(&stack.id))
+                    .and_then(|item| item.equip)
+                    .filter(|slot| equipment.get(*slot).is_some())
+                    .or_else(|| equipment.iter().next().map(|(slot, _)| slot));
+                if let Some(slot) = slot {
+                    unequip_events.send(UnequipItemEvent {
+                        entity: owner,
+                        slot,
+                    });
+                }
+            }
+            MenuAction::Drop | MenuAction::Throw => {
+                if let Some(slot) = selected {
+                    drop_events.send(DropItemEvent {
+                        entity: owner,
+                        slot,
+                        count: 1,
+                        throw: action == MenuAction::Throw,
+                    });
+                }
+            }
+            MenuAction::Sort => {
+                // Drop the stacks that don't fit anymore at the owner's feet
+                for stack in inventory.sort(&item_registry) {
+                    for _ in 0..stack.count {
+                        spawn_item_events.send(SpawnItemEvent {
+                            item: stack.id.clone(),
+                            position: transform.translation.truncate() + Vec2::new(0.0, 4.0),
+                            velocity: Vec2::new(0.0, throw_config.drop_velocity.y),
+                            dropped_by: Some(owner),
+                            thrown: None,
+                        });
+                    }
+                }
+                menu.cursor = 0;
+            }
+            MenuAction::Close => {
+                close_menu(&mut input, &mut gamestate);
+                return;
+            }
+        }
+    }
+}
+
+/// Rebuild the ui of the inventory menu when it or the inventory changed
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_inventory_menu(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    item_registry: Res<ItemRegistry>,
+    recipes: Res<Recipes>,
+    message: Res<ItemMessage>,
+    mut menu: ResMut<InventoryMenuState>,
+    mut used_events: EventReader<ItemUsedEvent>,
+    mut crafted_events: EventReader<CraftedEvent>,
+    owners: Query<(&Inventory, ChangeTrackers<Inventory>, &Equipment, ChangeTrackers<Equipment>)>,
+    ui: Query<Entity, With<InventoryMenuUi>>,
+) {
+    let (inventory, inventory_tracker, equipment, equipment_tracker) =
+        match menu.owner.and_then(|owner| owners.get(owner).ok()) {
+            Some(owner) => owner,
+            None => return,
+        };
+    // Show the results of the actions as they come in
+    let used = used_events.iter().count() + crafted_events.iter().count() > 0;
+    if !menu.dirty && !used && !inventory_tracker.is_changed() && !equipment_tracker.is_changed() {
+        return;
+    }
+    menu.dirty = false;
+    ui.for_each(|entity| commands.entity(entity).despawn_recursive());
+
+    let slots = visible_slots(inventory, &item_registry, menu.tab);
+    let craftable = if menu.tab == CRAFTING_TAB {
+        recipes.craftable(inventory).collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    let shown = slots.len().max(craftable.len());
+    menu.cursor = menu.cursor.min(shown.saturating_sub(1));
+    let (tab, cursor) = (menu.tab, menu.cursor);
+    let selected_recipe = craftable.get(cursor).copied();
+    let selected = slots
+        .get(cursor)
+        .and_then(|slot| inventory.get_item(*slot))
+        .map(|stack| stack.id.as_str())
+        .or_else(|| selected_recipe.map(|recipe| recipe.output.item.as_str()))
+        .and_then(|id| item_registry.get(id));
+    let item_name = |id: &str| {
+        item_registry
+            .get(id)
+            .map_or(id.to_string(), |item| item.name.clone())
+    };
+
+    let text = |value: &str, size: f32, color: Color| {
+        TextBundle {
+            text: Text::with_section(
+                value,
+                TextStyle {
+                    font: fonts.fira_sans.clone(),
+                    font_size: size,
+                    color,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        }
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(InventoryMenuUi)
+        .with_children(|parent| {
+            // The panel with the tabs and the grid
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        padding: Rect::all(Val::Px(16.0)),
+                        margin: Rect::all(Val::Px(8.0)),
+                        ..Default::default()
+                    },
+                    color: Color::BLACK.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(text("Inventory", 40.0, Color::WHITE));
+
+                    // The category tabs
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                margin: Rect::all(Val::Px(4.0)),
+                                ..Default::default()
+                            },
+                            color: Color::NONE.into(),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            for index in 0..TAB_COUNT {
+                                let label = match TABS.get(index) {
+                                    Some(None) => "All".to_string(),
+                                    Some(Some(category)) => format!("{:?}", category),
+                                    None => "Craft".to_string(),
+                                };
+                                let color = if index == tab {
+                                    Color::YELLOW
+                                } else {
+                                    Color::GRAY
+                                };
+                                parent
+                                    .spawn_bundle(NodeBundle {
+                                        style: Style {
+                                            margin: Rect::all(Val::Px(4.0)),
+                                            ..Default::default()
+                                        },
+                                        color: Color::NONE.into(),
+                                        ..Default::default()
+                                    })
+                                    .with_children(|parent| {
+                                        parent.spawn_bundle(text(&label, 20.0, color));
+                                    });
+                            }
+                        });
+
+                    // The grid of item icons, a row at a time
+                    for (row_index, row) in slots.chunks(GRID_COLUMNS).enumerate() {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                color: Color::NONE.into(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                for (column, slot) in row.iter().enumerate() {
+                                    let stack = match inventory.get_item(*slot) {
+                                        Some(stack) => stack,
+                                        None => continue,
+                                    };
+                                    let highlighted =
+                                        row_index * GRID_COLUMNS + column == cursor;
+                                    parent
+                                        .spawn_bundle(NodeBundle {
+                                            style: Style {
+                                                size: Size::new(Val::Px(56.0), Val::Px(56.0)),
+                                                margin: Rect::all(Val::Px(4.0)),
+                                                justify_content: JustifyContent::Center,
+                                                align_items: AlignItems::Center,
+                                                ..Default::default()
+                                            },
+                                            color: if highlighted {
+                                                Color::rgb(0.6, 0.5, 0.1).into()
+                                            } else {
+                                                Color::rgb(0.2, 0.2, 0.2).into()
+                                            },
+                                            ..Default::default()
+                                        })
+                                        .with_children(|parent| {
+                                            if let Some(item) = item_registry.get(&stack.id) {
+                                                parent.spawn_bundle(ImageBundle {
+                                                    style: Style {
+                                                        size: Size::new(
+                                                            Val::Px(40.0),
+                                                            Val::Px(40.0),
+                                                        ),
+                                                        ..Default::default()
+                                                    },
+                                                    image: UiImage(item.icon.clone()),
+                                                    ..Default::default()
+                                                });
+                                            }
+                                            if stack.count > 1 {
+                                                parent.spawn_bundle(text(
+                                                    &stack.count.to_string(),
+                                                    16.0,
+                                                    Color::WHITE,
+                                                ));
+                                            }
+                                        });
+                                }
+                            });
+                    }
+                    // The recipes that can be crafted, a row each
+                    for (index, recipe) in craftable.iter().enumerate() {
+                        let inputs = recipe
+                            .inputs
+                            .iter()
+                            .map(|input| format!("{} x{}", item_name(&input.item), input.count))
+                            .collect::<Vec<_>>()
+                            .join(" + ");
+                        let color = if index == cursor {
+                            Color::YELLOW
+                        } else {
+                            Color::WHITE
+                        };
+                        parent.spawn_bundle(text(
+                            &format!(
+                                "{} x{}  <-  {}",
+                                item_name(&recipe.output.item),
+                                recipe.output.count,
+                                inputs
+                            ),
+                            20.0,
+                            color,
+                        ));
+                    }
+                    if tab == CRAFTING_TAB && craftable.is_empty() {
+                        parent.spawn_bundle(text("Nothing to craft", 20.0, Color::GRAY));
+                    } else if tab != CRAFTING_TAB && slots.is_empty() {
+                        parent.spawn_bundle(text("Nothing here", 20.0, Color::GRAY));
+                    }
+                });
+
+            // The description panel
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        size: Size::new(Val::Px(320.0), Val::Auto),
+                        padding: Rect::all(Val::Px(16.0)),
+                        margin: Rect::all(Val::Px(8.0)),
+                        ..Default::default()
+                    },
+                    color: Color::BLACK.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    if let Some(item) = selected {
+                        parent.spawn_bundle(text(&item.name, 28.0, Color::WHITE));
+                        parent.spawn_bundle(text(
+                            &format!("{:?}", item.category),
+                            16.0,
+                            Color::GRAY,
+                        ));
+                        parent.spawn_bundle(text(&item.description, 18.0, Color::WHITE));
+                        if let Some(slot) = item.equip {
+                            parent.spawn_bundle(text(
+                                &format!("Equips as {:?}", slot),
+                                16.0,
+                                Color::GRAY,
+                            ));
+                        }
+                    }
+
+                    // The equipped items
+                    parent.spawn_bundle(text("Equipment", 24.0, Color::WHITE));
+                    for slot in EquipSlot::ALL {
+                        let name = equipment.get(slot).map_or("-", |id| {
+                            item_registry
+                                .get(id)
+                                .map_or(id.as_str(), |item| item.name.as_str())
+                        });
+                        parent.spawn_bundle(text(
+                            &format!("{:?}: {}", slot, name),
+                            16.0,
+                            Color::GRAY,
+                        ));
+                    }
+
+                    // The result of the last action
+                    if message.remaining > 0.0 {
+                        parent.spawn_bundle(text(&message.text, 16.0, Color::YELLOW));
+                    }
+
+                    parent.spawn_bundle(text(
+                        "Enter use/craft, E equip, X unequip, G drop, T throw, R sort, Z/C tabs, Esc close",
+                        14.0,
+                        Color::GRAY,
+                    ));
+                });
+        });
+}
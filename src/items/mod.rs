@@ -1,16 +1,143 @@
-use self::inventory::{Inventory, spawn_inventory_menu};
-use crate::{prelude::{Collider, CollidingEntities, PhysicsBodyBundle, GameState}, levels::LevelObject};
+use self::{
+    container::{
+        close_container_menu, draw_container_menu, handle_container_menu_input,
+        handle_transfers, open_containers, ContainerMenuState, ItemsTransferredEvent,
+        TransferFailedEvent, TransferItemEvent,
+    },
+    crafting::{
+        handle_crafting, rebuild_recipes, show_craft_messages, CraftItemEvent, CraftedEvent,
+        RecipeFile, RecipeHandles, RecipeLoader, Recipes,
+    },
+    data::{check_data_files, load_data_files},
+    definition::{
+        rebuild_item_registry, ItemDefinitionHandles, ItemDefinitions, ItemDefinitionsLoader,
+    },
+    effect::{
+        apply_buff_effects, apply_grant_effects, apply_heal_effects,
+        dispatch_item_uses, finish_item_uses, show_item_messages, use_item_input, ItemEffect,
+        ItemMessage, ItemUsedEvent, UseItemEvent,
+    },
+    equipment::{
+        handle_equip_events, reveal_secrets, update_stat_modifiers, EquipItemEvent, EquipSlot,
+        StatModifier, UnequipItemEvent,
+    },
+    inventory::{send_inventory_changes, Inventory, InventoryChanged},
+    loot::{
+        drop_loot_on_death, open_chests, rebuild_loot_tables, roll_loot, DropLootEvent,
+        LootConfig, LootTableFile, LootTableHandles, LootTableLoader, LootTables,
+    },
+    menu::{
+        close_inventory_menu, draw_inventory_menu, handle_inventory_menu_input,
+        open_inventory_menu, InventoryMenuState,
+    },
+    pickup::{
+        attract_items, handle_pickups, read_interactions, ItemPickedUp, PickupMode,
+        PickupRejected,
+    },
+    shop::{
+        close_shop_menu, draw_shop_menu, handle_shop_menu_input, open_shops, restock_shops,
+        ShopMenuState,
+    },
+    throw::{
+        handle_drops, handle_thrown_hits, tick_pickup_cooldowns, DropItemEvent, PickupCooldown,
+        ThrowConfig, Thrown,
+    },
+};
+use crate::{
+    levels::LevelObject,
+    prelude::{
+        AbilityGrant, Collider, CollidingEntities, GameState, MeleeAttack, PhysicsBodyBundle,
+        RangedAttack,
+    },
+};
 use bevy::prelude::*;
 use hashbrown::HashMap;
-use std::ops::{Deref, DerefMut};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+pub mod container;
+pub mod crafting;
+pub mod data;
+pub mod definition;
+pub mod effect;
+pub mod equipment;
 pub mod inventory;
+pub mod loot;
+pub mod menu;
+pub mod pickup;
+pub mod shop;
+pub mod throw;
+
+/// The id an item is registered under in the [`ItemRegistry`]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ItemId(pub String);
+
+impl ItemId {
+    /// Creates a new [`ItemId`]
+    pub fn new(id: &str) -> Self {
+        Self(id.into())
+    }
+
+    /// Get the id as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for ItemId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Borrow<str> for ItemId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for ItemId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<String> for ItemId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl PartialEq<str> for ItemId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for ItemId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
 
 /// The registry of items
 #[derive(Debug, Default, Clone)]
-pub struct ItemRegistry(pub HashMap<String, ItemDescriptor>);
+pub struct ItemRegistry(pub HashMap<ItemId, ItemDescriptor>);
 
 impl Deref for ItemRegistry {
-    type Target = HashMap<String, ItemDescriptor>;
+    type Target = HashMap<ItemId, ItemDescriptor>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -24,7 +151,7 @@ impl DerefMut for ItemRegistry {
 }
 
 /// The category of an item
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
 pub enum ItemCategory {
     Misc,
     Food,
@@ -40,28 +167,54 @@ impl Default for ItemCategory {
 }
 
 /// Describes an item in the inventory
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ItemDescriptor {
+    pub id: ItemId,
     pub name: String,
     pub description: String,
     pub icon: Handle<Image>,
     pub category: ItemCategory,
+    pub grants: Option<AbilityGrant>,
+    // The number of units that fit in one inventory slot
+    pub max_stack: u32,
+    // What the item does when it is used
+    pub effects: Vec<ItemEffect>,
+    // Whether the item is used up when it is used
+    pub consumable: bool,
+    // How the item is picked up
+    pub pickup: PickupMode,
+    // The slot the item is equipped in, if it can be equipped
+    pub equip: Option<EquipSlot>,
+    // The stat changes while the item is equipped
+    pub modifiers: Vec<StatModifier>,
+    // The coins a shop pays for the item, if it can be sold
+    pub sell_price: Option<u32>,
+    // The coins picking the item up puts in the wallet, 0 if it goes into the inventory
+    pub coins: u32,
+    // The melee attack of a weapon
+    pub attack: Option<MeleeAttack>,
+    // The ranged attack of a weapon
+    pub ranged: Option<RangedAttack>,
 }
 
-/// The item that the entity represents
+/// The item that the entity represents, its descriptor is looked up in the [`ItemRegistry`]
 #[derive(Debug, Clone, Component)]
-pub struct RepresentingItem(pub ItemDescriptor);
+pub struct RepresentingItem(pub ItemId);
 
 /// An event that spawns an item entity
-#[derive(Debug, Clone)]
+#[derive(Debug, Default, Clone)]
 pub struct SpawnItemEvent {
-    pub item: String,
+    pub item: ItemId,
     pub position: Vec2,
     pub velocity: Vec2,
+    // The entity that dropped the item, it can't pick the item up again right away
+    pub dropped_by: Option<Entity>,
+    // Whether the item was thrown and hits what it touches
+    pub thrown: Option<Thrown>,
 }
 
 impl Deref for RepresentingItem {
-    type Target = ItemDescriptor;
+    type Target = ItemId;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -74,91 +227,189 @@ pub struct ItemPlugin;
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ItemRegistry>();
+        app.init_resource::<ItemDefinitionHandles>();
+        app.add_asset::<ItemDefinitions>();
+        app.init_asset_loader::<ItemDefinitionsLoader>();
+        app.init_resource::<ItemMessage>();
         app.add_event::<SpawnItemEvent>();
-        app.add_startup_system(register_items);
+        app.add_event::<UseItemEvent>();
+        app.add_event::<ItemUsedEvent>();
+        app.add_event::<EquipItemEvent>();
+        app.add_event::<UnequipItemEvent>();
+        app.add_system(rebuild_item_registry.label("rebuild_item_registry"));
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Startup)
+                .with_system(load_data_files::<ItemDefinitions>),
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Startup)
+                .with_system(check_data_files::<ItemDefinitions>),
+        );
+        app.init_resource::<InventoryMenuState>();
+        app.init_resource::<ThrowConfig>();
+        app.add_event::<DropItemEvent>();
+        app.add_event::<ItemPickedUp>();
+        app.add_event::<PickupRejected>();
+        app.init_resource::<LootTables>();
+        app.init_resource::<LootTableHandles>();
+        app.init_resource::<LootConfig>();
+        app.add_asset::<LootTableFile>();
+        app.init_asset_loader::<LootTableLoader>();
+        app.add_event::<DropLootEvent>();
+        app.add_system(rebuild_loot_tables.after("rebuild_item_registry"));
+        app.add_system(roll_loot.label("roll_loot").before("spawn_items"));
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Startup).with_system(load_data_files::<LootTableFile>),
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Startup).with_system(check_data_files::<LootTableFile>),
+        );
+        app.add_system(handle_drops.label("handle_drops"));
+        app.add_system(handle_item_spawning.label("spawn_items").after("handle_drops"));
         app.add_system_set(SystemSet::on_update(GameState::Gameplay) 
-            .with_system(handle_item_collisions)
-            .with_system(handle_item_spawning)
+            .with_system(read_interactions.label("read_interactions").after("update_input"))
+            .with_system(
+                handle_pickups
+                    .after("handle_collision")
+                    .after("read_interactions"),
+            )
+            .with_system(attract_items.before("apply_gravity"))
+            .with_system(handle_thrown_hits.after("handle_collision"))
+            .with_system(tick_pickup_cooldowns)
+            .with_system(use_item_input.label("use_item_input").after("update_input"))
+            .with_system(reveal_secrets.after("update_stat_modifiers"))
+            .with_system(open_shops.after("handle_collision").after("read_interactions"))
+            .with_system(restock_shops)
+            .with_system(open_containers.after("handle_collision").after("read_interactions"))
+            .with_system(drop_loot_on_death.before("roll_loot").after("apply_damage"))
+            .with_system(
+                open_chests
+                    .before("roll_loot")
+                    .after("handle_collision")
+                    .after("read_interactions"),
+            )
         );
+        // Items can be used from the world and from the inventory menu
+        app.add_system_set(item_use_systems(GameState::Gameplay));
+        app.add_system_set(item_use_systems(GameState::Menu));
         app.add_system_set(
             SystemSet::on_enter(GameState::Menu)
-                .with_system(spawn_inventory_menu)
+                .with_system(open_inventory_menu)
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Menu)
+                .with_system(handle_inventory_menu_input.before("dispatch_item_uses"))
+                .with_system(draw_inventory_menu.after("show_item_messages"))
+        );
+        app.add_system_set(
+            SystemSet::on_exit(GameState::Menu)
+                .with_system(close_inventory_menu)
+        );
+        app.init_resource::<Recipes>();
+        app.init_resource::<RecipeHandles>();
+        app.add_asset::<RecipeFile>();
+        app.init_asset_loader::<RecipeLoader>();
+        app.add_event::<CraftItemEvent>();
+        app.add_event::<CraftedEvent>();
+        app.add_system(rebuild_recipes.after("rebuild_item_registry"));
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Startup).with_system(load_data_files::<RecipeFile>),
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Startup).with_system(check_data_files::<RecipeFile>),
+        );
+        app.init_resource::<ContainerMenuState>();
+        app.add_event::<TransferItemEvent>();
+        app.add_event::<ItemsTransferredEvent>();
+        app.add_event::<TransferFailedEvent>();
+        app.add_system(handle_transfers.label("handle_transfers"));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Container)
+                .with_system(handle_container_menu_input.before("handle_transfers"))
+                .with_system(draw_container_menu.after("handle_transfers"))
+        );
+        app.add_system_set(
+            SystemSet::on_exit(GameState::Container).with_system(close_container_menu),
+        );
+        app.init_resource::<ShopMenuState>();
+        app.add_system_set(
+            SystemSet::on_update(GameState::Shop)
+                .with_system(handle_shop_menu_input.label("handle_shop_menu_input"))
+                .with_system(draw_shop_menu.after("handle_shop_menu_input"))
+        );
+        app.add_system_set(SystemSet::on_exit(GameState::Shop).with_system(close_shop_menu));
+        // Send the inventory changes after every system had the chance to make them
+        app.add_event::<InventoryChanged>();
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            send_inventory_changes.label("send_inventory_changes"),
         );
     }
 }
 
-/// Register the items in the inventory
-fn register_items(asset_server: Res<AssetServer>, mut item_registry: ResMut<ItemRegistry>) {
-    // Register the mirror item
-    item_registry.insert(
-        "mirror".into(),
-        ItemDescriptor {
-            name: "Mirror".to_string(),
-            description: "A cute little mirror, in the impractical shape of a circle".into(),
-            icon: asset_server.load("sprites/items/misc/mirror.png"),
-            category: ItemCategory::Misc,
-        },
-    );
-    item_registry.insert(
-        "monocle".into(),
-        ItemDescriptor {
-            name: "Monocle".to_string(),
-            description: "For people who are only half sightless.".into(),
-            icon: asset_server.load("sprites/items/misc/monocle.png"),
-            category: ItemCategory::Misc,
-        },
-    );
-    // Register the bean
-    item_registry.insert(
-        "bean".into(),
-        ItemDescriptor {
-            name: "Bean".to_string(),
-            description: "A bean, for those who like beans.".into(),
-            icon: asset_server.load("sprites/items/food/bean.png"),
-            category: ItemCategory::Food,
-        },
-    );
-}
-
-/// Handle the collisions between the item entities and an inventory holder
-fn handle_item_collisions(
-    mut commands: Commands,
-    mut inventories: Query<&mut Inventory>,
-    item_entities: Query<(Entity, &RepresentingItem, &CollidingEntities)>,
-) {
-    item_entities.for_each(|(entity, item, colliding_entities)| {
-        // If the item is colliding with an inventory holder
-        if !colliding_entities.0.is_empty() {
-            // Loop over the colliding entities
-            colliding_entities.0.iter().for_each(|colliding_entity| {
-                // Add the item to the inventory
-                if let Ok(mut inventory) = inventories.get_mut(*colliding_entity) {
-                    inventory.add_item(item.0.clone());
-                    // Destroy the item
-                    commands.entity(entity).despawn();
-                }
-            });
-        }
-    });
+/// The systems that apply the item uses and equipment changes in a state
+fn item_use_systems(state: GameState) -> SystemSet {
+    SystemSet::on_update(state)
+        .with_system(
+            dispatch_item_uses
+                .label("dispatch_item_uses")
+                .after("use_item_input"),
+        )
+        .with_system(apply_heal_effects.label("apply_item_effects"))
+        .with_system(apply_buff_effects.label("apply_item_effects"))
+        .with_system(apply_grant_effects.label("apply_item_effects"))
+        .with_system(finish_item_uses.label("finish_item_uses").after("apply_item_effects"))
+        .with_system(
+            handle_crafting
+                .label("handle_crafting")
+                .after("dispatch_item_uses"),
+        )
+        .with_system(
+            handle_equip_events
+                .label("handle_equip_events")
+                .after("dispatch_item_uses"),
+        )
+        .with_system(
+            update_stat_modifiers
+                .label("update_stat_modifiers")
+                .after("handle_equip_events"),
+        )
+        .with_system(
+            show_craft_messages
+                .label("show_craft_messages")
+                .after("handle_crafting"),
+        )
+        .with_system(
+            show_item_messages
+                .label("show_item_messages")
+                .after("finish_item_uses")
+                .after("handle_equip_events")
+                .after("show_craft_messages"),
+        )
 }
 
 /// Handle the item spawning event
 fn handle_item_spawning(
     item_registry: ResMut<ItemRegistry>,
+    throw_config: Res<ThrowConfig>,
     mut commands: Commands,
     mut item_spawning: EventReader<SpawnItemEvent>,
 ) {
     for event in item_spawning.iter() {
-        // Get the item
-        let item = item_registry
-            .get(&event.item)
-            .expect(&format!("Item not found: {}", event.item));
+        // Get the item, unknown items aren't spawned
+        let item = match item_registry.get(&event.item) {
+            Some(item) => item,
+            None => {
+                error!("Can't spawn the unknown item {}", event.item);
+                continue;
+            }
+        };
         // Spawn the entity
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: item.icon.clone(),
-                ..Default::default()
-            })
+        let mut entity = commands.spawn_bundle(SpriteBundle {
+            texture: item.icon.clone(),
+            ..Default::default()
+        });
+        entity
             .insert_bundle(PhysicsBodyBundle {
                 velocity: event.velocity.into(),
                 friction: Vec2::new(3.0, 0.0).into(),
@@ -170,8 +421,40 @@ fn handle_item_spawning(
                 ..Default::default()
             })
             .insert(Transform::from_xyz(event.position.x, event.position.y, 0.0))
-            .insert(RepresentingItem(item.clone()))
+            .insert(RepresentingItem(item.id.clone()))
             .insert(CollidingEntities::default())
             .insert(LevelObject);
+        if let Some(dropped_by) = event.dropped_by {
+            entity.insert(PickupCooldown {
+                ignored: dropped_by,
+                remaining: throw_config.pickup_cooldown,
+            });
+        }
+        if let Some(thrown) = &event.thrown {
+            entity.insert(thrown.clone());
+        }
+    }
+}
+
+/// Creates the descriptor of an item that does nothing, for the tests
+#[cfg(test)]
+pub(crate) fn test_item(id: &str, category: ItemCategory, max_stack: u32) -> ItemDescriptor {
+    ItemDescriptor {
+        id: ItemId::new(id),
+        name: id.into(),
+        description: String::new(),
+        icon: Handle::default(),
+        category,
+        grants: None,
+        max_stack,
+        effects: vec![],
+        consumable: false,
+        pickup: PickupMode::Touch,
+        equip: None,
+        modifiers: vec![],
+        sell_price: None,
+        coins: 0,
+        attack: None,
+        ranged: None,
     }
 }
@@ -0,0 +1,215 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{
+    container::Container,
+    inventory::Inventory,
+    shop::Wallet,
+    throw::{PickupCooldown, Thrown},
+    ItemId, ItemRegistry, RepresentingItem,
+};
+use crate::{
+    physics::component::{CollidingEntities, Velocity},
+    player::{
+        abilities::{Abilities, AbilityUnlockedEvent, GrantTrigger},
+        component::PlayerState,
+    },
+};
+
+/// How an item is picked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PickupMode {
+    // The item is picked up by touching it
+    Touch,
+    // The item is picked up by touching it and pressing the interact button
+    Press,
+}
+
+impl Default for PickupMode {
+    fn default() -> Self {
+        Self::Touch
+    }
+}
+
+/// Why an item wasn't picked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickupRejectReason {
+    InventoryFull,
+}
+
+/// An event that is sent when an item is picked up
+#[derive(Debug, Clone)]
+pub struct ItemPickedUp {
+    pub holder: Entity,
+    pub item: ItemId,
+}
+
+/// An event that is sent when a holder touches an item it can't pick up
+#[derive(Debug, Clone)]
+pub struct PickupRejected {
+    pub holder: Entity,
+    pub item: ItemId,
+    pub reason: PickupRejectReason,
+}
+
+/// Pulls the items within a radius towards the entity
+#[derive(Debug, Clone, Component)]
+pub struct ItemMagnet {
+    pub radius: f32,
+    // The speed the items fly towards the entity with
+    pub speed: f32,
+}
+
+/// The holders that were already told they can't pick up the item
+/// They are only told again after they stopped touching it
+#[derive(Debug, Default, Clone, Component)]
+pub struct RejectedHolders(pub Vec<Entity>);
+
+/// Track the presses of the interact button
+pub(crate) fn read_interactions(mut players: Query<&mut PlayerState>) {
+    players.for_each_mut(|mut state| {
+        state.interact_pressed = state.input.is_interacting && !state.interact_held;
+        state.interact_held = state.input.is_interacting;
+    });
+}
+
+/// Move the items the holders touch into their inventories, containers don't pick items up
+/// An item is only taken by the first holder that has room for it
+pub(crate) fn handle_pickups(
+    mut commands: Commands,
+    item_registry: Res<ItemRegistry>,
+    mut holders: Query<
+        (
+            &mut Inventory,
+            Option<&mut Wallet>,
+            Option<&mut Abilities>,
+            Option<&PlayerState>,
+        ),
+        Without<Container>,
+    >,
+    mut items: Query<(
+        Entity,
+        &RepresentingItem,
+        &CollidingEntities,
+        Option<&PickupCooldown>,
+        Option<&mut RejectedHolders>,
+    )>,
+    mut picked_up_events: EventWriter<ItemPickedUp>,
+    mut rejected_events: EventWriter<PickupRejected>,
+    mut unlock_events: EventWriter<AbilityUnlockedEvent>,
+) {
+    items.for_each_mut(|(entity, item_id, colliding_entities, cooldown, rejected)| {
+        let item = match item_registry.get(&item_id.0) {
+            Some(item) => item,
+            None => return,
+        };
+        let mut rejected_now = vec![];
+
+        for holder in colliding_entities.iter() {
+            // The dropper has to wait before picking the item up again
+            if cooldown.map_or(false, |cooldown| cooldown.ignored == *holder) {
+                continue;
+            }
+            let (mut inventory, wallet, abilities, state) = match holders.get_mut(*holder) {
+                Ok(holder) => holder,
+                Err(_) => continue,
+            };
+            // Important items need a button press
+            if item.pickup == PickupMode::Press
+                && !state.map_or(false, |state| state.interact_pressed)
+            {
+                continue;
+            }
+
+            // Coins go straight into the wallet
+            if let (Some(mut wallet), true) = (wallet, item.coins > 0) {
+                wallet.add(item.coins);
+                picked_up_events.send(ItemPickedUp {
+                    holder: *holder,
+                    item: item.id.clone(),
+                });
+                commands.entity(entity).despawn();
+                return;
+            }
+
+            // Leave the item in the world if there is no room for it
+            if inventory.add_item(item, 1) > 0 {
+                rejected_now.push(*holder);
+                continue;
+            }
+
+            // Unlock the ability the item grants on pickup
+            if let (Some(mut abilities), Some(grant)) = (abilities, item.grants) {
+                if grant.on == GrantTrigger::Collect && abilities.grant(grant.ability) {
+                    unlock_events.send(AbilityUnlockedEvent {
+                        entity: *holder,
+                        ability: grant.ability,
+                    });
+                }
+            }
+            picked_up_events.send(ItemPickedUp {
+                holder: *holder,
+                item: item.id.clone(),
+            });
+            commands.entity(entity).despawn();
+            return;
+        }
+
+        // Tell each holder about the rejection once per touch
+        let previous = rejected.as_ref().map_or(&[][..], |rejected| rejected.0.as_slice());
+        for holder in &rejected_now {
+            if !previous.contains(holder) {
+                rejected_events.send(PickupRejected {
+                    holder: *holder,
+                    item: item.id.clone(),
+                    reason: PickupRejectReason::InventoryFull,
+                });
+            }
+        }
+        if previous != rejected_now.as_slice() {
+            match rejected {
+                Some(mut rejected) => rejected.0 = rejected_now,
+                None => {
+                    commands.entity(entity).insert(RejectedHolders(rejected_now));
+                }
+            }
+        }
+    });
+}
+
+/// Pull the items towards the magnets around them
+pub(crate) fn attract_items(
+    item_registry: Res<ItemRegistry>,
+    magnets: Query<(Entity, &ItemMagnet, &Transform)>,
+    mut items: Query<
+        (
+            &RepresentingItem,
+            &Transform,
+            &mut Velocity,
+            Option<&PickupCooldown>,
+        ),
+        Without<Thrown>,
+    >,
+) {
+    items.for_each_mut(|(item_id, transform, mut velocity, cooldown)| {
+        // Important items stay where they are
+        let pickup = item_registry.get(&item_id.0).map(|item| item.pickup);
+        if pickup != Some(PickupMode::Touch) {
+            return;
+        }
+        let position = transform.translation.truncate();
+
+        // Fly towards the closest magnet in range
+        let closest = magnets
+            .iter()
+            .filter(|(entity, _, _)| cooldown.map_or(true, |cooldown| cooldown.ignored != *entity))
+            .map(|(_, magnet, magnet_transform)| {
+                (magnet, magnet_transform.translation.truncate() - position)
+            })
+            .filter(|(magnet, offset)| offset.length() <= magnet.radius)
+            .min_by(|(_, a), (_, b)| a.length().total_cmp(&b.length()));
+        if let Some((magnet, offset)) = closest {
+            velocity.0 = offset.normalize_or_zero() * magnet.speed;
+        }
+    });
+}
@@ -0,0 +1,536 @@
+use bevy::prelude::*;
+
+use super::{inventory::Inventory, menu::{close_menu, MenuInput}, ItemId, ItemRegistry};
+use crate::{
+    assets::Fonts,
+    physics::component::CollidingEntities,
+    player::component::{Player, PlayerState},
+    simulation::SimulationTime,
+    state::GameState,
+};
+
+/// The price a shop asks for an item it bought, as a multiple of the sell price
+const BUYBACK_MARKUP: u32 = 2;
+
+/// The coins an entity carries
+#[derive(Debug, Default, Clone, Component)]
+pub struct Wallet {
+    pub coins: u32,
+}
+
+impl Wallet {
+    /// Add coins to the wallet
+    pub fn add(&mut self, coins: u32) {
+        self.coins = self.coins.saturating_add(coins);
+    }
+
+    /// Take coins out of the wallet, returns false if there aren't enough
+    pub fn spend(&mut self, coins: u32) -> bool {
+        if self.coins < coins {
+            return false;
+        }
+        self.coins -= coins;
+        true
+    }
+}
+
+/// An item a shop sells
+#[derive(Debug, Clone)]
+pub struct ShopStock {
+    pub item: ItemId,
+    pub price: u32,
+    // The units left, `None` never runs out
+    pub quantity: Option<u32>,
+    // The units the stock restocks up to
+    pub max_quantity: u32,
+    // The time in seconds it takes to restock one unit, `None` never restocks
+    pub restock_time: Option<f32>,
+    // The time spent restocking the next unit
+    pub restock_timer: f32,
+}
+
+impl ShopStock {
+    /// Creates a new [`ShopStock`] that never runs out
+    pub fn unlimited(item: &str, price: u32) -> Self {
+        Self {
+            item: item.into(),
+            price,
+            quantity: None,
+            max_quantity: 0,
+            restock_time: None,
+            restock_timer: 0.0,
+        }
+    }
+
+    /// Creates a new [`ShopStock`] with a limited quantity
+    pub fn limited(item: &str, price: u32, quantity: u32) -> Self {
+        Self {
+            quantity: Some(quantity),
+            max_quantity: quantity,
+            ..Self::unlimited(item, price)
+        }
+    }
+
+    /// Restock one unit every `seconds` until the stock is full again
+    pub fn restocking(mut self, seconds: f32) -> Self {
+        self.restock_time = Some(seconds);
+        self
+    }
+
+    /// Check if the stock ran out
+    pub fn is_sold_out(&self) -> bool {
+        self.quantity == Some(0)
+    }
+}
+
+/// Why a purchase or sale didn't go through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShopError {
+    // There is no item in the stock or inventory slot
+    EmptySlot,
+    // The item isn't in the item registry
+    UnknownItem,
+    // The shop has no units left
+    SoldOut,
+    // The wallet doesn't hold enough coins
+    NotEnoughCoins,
+    // The inventory has no room for the item
+    InventoryFull,
+    // The item has no sell price
+    NotSellable,
+}
+
+/// A shopkeeper that buys and sells items
+#[derive(Debug, Clone, Component)]
+pub struct Shop {
+    pub name: String,
+    pub stock: Vec<ShopStock>,
+}
+
+impl Shop {
+    /// Buy one unit of the item in a stock slot
+    /// Returns the id of the item that was bought
+    pub fn buy(
+        &mut self,
+        index: usize,
+        wallet: &mut Wallet,
+        inventory: &mut Inventory,
+        item_registry: &ItemRegistry,
+    ) -> Result<ItemId, ShopError> {
+        let stock = self.stock.get_mut(index).ok_or(ShopError::EmptySlot)?;
+        let item = item_registry
+            .get(&stock.item)
+            .ok_or(ShopError::UnknownItem)?;
+        if stock.is_sold_out() {
+            return Err(ShopError::SoldOut);
+        }
+        if wallet.coins < stock.price {
+            return Err(ShopError::NotEnoughCoins);
+        }
+        if inventory.add_item(item, 1) > 0 {
+            return Err(ShopError::InventoryFull);
+        }
+
+        wallet.spend(stock.price);
+        if let Some(quantity) = &mut stock.quantity {
+            *quantity -= 1;
+        }
+        Ok(item.id.clone())
+    }
+
+    /// Sell one unit of the item in an inventory slot to the shop
+    /// Returns the id of the item and the coins it was sold for
+    pub fn sell(
+        &mut self,
+        slot: usize,
+        wallet: &mut Wallet,
+        inventory: &mut Inventory,
+        item_registry: &ItemRegistry,
+    ) -> Result<(ItemId, u32), ShopError> {
+        let stack = inventory.get_item(slot).ok_or(ShopError::EmptySlot)?;
+        let item = item_registry
+            .get(&stack.id)
+            .ok_or(ShopError::UnknownItem)?;
+        let price = item.sell_price.ok_or(ShopError::NotSellable)?;
+
+        inventory.remove_item(slot, 1);
+        wallet.add(price);
+
+        // The shop puts the item up for sale
+        match self.stock.iter_mut().find(|stock| stock.item == item.id) {
+            Some(stock) => {
+                if let Some(quantity) = &mut stock.quantity {
+                    *quantity += 1;
+                }
+            }
+            None => self
+                .stock
+                .push(ShopStock::limited(&item.id, price * BUYBACK_MARKUP, 1)),
+        }
+        Ok((item.id.clone(), price))
+    }
+}
+
+/// Whether the shop menu shows the shop's stock or the customer's inventory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShopMode {
+    Buy,
+    Sell,
+}
+
+impl Default for ShopMode {
+    fn default() -> Self {
+        Self::Buy
+    }
+}
+
+/// The marker component for the ui of the shop menu
+#[derive(Debug, Default, Clone, Component)]
+pub struct ShopMenuUi;
+
+/// The state of the shop menu
+#[derive(Debug, Default, Clone)]
+pub struct ShopMenuState {
+    // The shop that is open
+    pub shop: Option<Entity>,
+    // The player that is shopping
+    pub customer: Option<Entity>,
+    pub mode: ShopMode,
+    // The index of the selected row
+    pub cursor: usize,
+    // The result of the last purchase or sale
+    pub message: String,
+    // Whether the ui has to be rebuilt
+    pub dirty: bool,
+}
+
+/// An action the player can take in the shop menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShopAction {
+    Move(i32),
+    SwitchMode,
+    Confirm,
+    Close,
+}
+
+/// Open the shop the player touches and presses the interact button at
+pub(crate) fn open_shops(
+    players: Query<(Entity, &PlayerState, &CollidingEntities), With<Player>>,
+    shops: Query<(), With<Shop>>,
+    mut menu: ResMut<ShopMenuState>,
+    mut gamestate: ResMut<State<GameState>>,
+) {
+    for (player, state, colliding_entities) in players.iter() {
+        if !state.interact_pressed {
+            continue;
+        }
+        let shop = colliding_entities
+            .iter()
+            .find(|entity| shops.get(**entity).is_ok());
+        if let Some(shop) = shop {
+            // Another state change like a game over can already be queued this frame
+            if *gamestate.current() != GameState::Shop
+                && gamestate.set(GameState::Shop).is_err()
+            {
+                return;
+            }
+            *menu = ShopMenuState {
+                shop: Some(*shop),
+                customer: Some(player),
+                dirty: true,
+                ..Default::default()
+            };
+            return;
+        }
+    }
+}
+
+/// Removes the ui of the shop menu
+pub(crate) fn close_shop_menu(
+    mut commands: Commands,
+    mut menu: ResMut<ShopMenuState>,
+    ui: Query<Entity, With<ShopMenuUi>>,
+) {
+    ui.for_each(|entity| commands.entity(entity).despawn_recursive());
+    menu.shop = None;
+    menu.customer = None;
+}
+
+/// Every action of the shop menu, the one that closes it first
+const ACTIONS: [ShopAction; 5] = [
+    ShopAction::Close,
+    ShopAction::Move(-1),
+    ShopAction::Move(1),
+    ShopAction::SwitchMode,
+    ShopAction::Confirm,
+];
+
+/// The keys of the shop menu actions
+const KEYS: [(KeyCode, ShopAction); 12] = [
+    (KeyCode::W, ShopAction::Move(-1)),
+    (KeyCode::Up, ShopAction::Move(-1)),
+    (KeyCode::S, ShopAction::Move(1)),
+    (KeyCode::Down, ShopAction::Move(1)),
+    (KeyCode::A, ShopAction::SwitchMode),
+    (KeyCode::Left, ShopAction::SwitchMode),
+    (KeyCode::D, ShopAction::SwitchMode),
+    (KeyCode::Right, ShopAction::SwitchMode),
+    (KeyCode::Return, ShopAction::Confirm),
+    (KeyCode::Space, ShopAction::Confirm),
+    (KeyCode::Escape, ShopAction::Close),
+    (KeyCode::Q, ShopAction::Close),
+];
+
+/// The gamepad buttons of the shop menu actions
+const BUTTONS: [(GamepadButtonType, ShopAction); 7] = [
+    (GamepadButtonType::DPadUp, ShopAction::Move(-1)),
+    (GamepadButtonType::DPadDown, ShopAction::Move(1)),
+    (GamepadButtonType::DPadLeft, ShopAction::SwitchMode),
+    (GamepadButtonType::DPadRight, ShopAction::SwitchMode),
+    (GamepadButtonType::South, ShopAction::Confirm),
+    (GamepadButtonType::East, ShopAction::Close),
+    (GamepadButtonType::Select, ShopAction::Close),
+];
+
+/// Get the display name of an item
+fn item_name<'a>(item_registry: &'a ItemRegistry, id: &'a str) -> &'a str {
+    item_registry.get(id).map_or(id, |item| item.name.as_str())
+}
+
+/// Handle the input of the shop menu
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_shop_menu_input(
+    mut input: MenuInput,
+    item_registry: Res<ItemRegistry>,
+    mut menu: ResMut<ShopMenuState>,
+    mut gamestate: ResMut<State<GameState>>,
+    mut shops: Query<&mut Shop>,
+    mut customers: Query<(&mut Wallet, &mut Inventory)>,
+) {
+    let actions = input.read(menu.customer, &ACTIONS, &KEYS, &BUTTONS);
+    if actions.is_empty() {
+        return;
+    }
+
+    let open = menu.shop.zip(menu.customer).and_then(|(shop, customer)| {
+        shops
+            .get_mut(shop)
+            .ok()
+            .zip(customers.get_mut(customer).ok())
+    });
+    let (mut shop, (mut wallet, mut inventory)) = match open {
+        Some(open) => open,
+        None => {
+            if *gamestate.current() != GameState::Gameplay {
+                gamestate.set(GameState::Gameplay).unwrap();
+            }
+            return;
+        }
+    };
+
+    for action in actions {
+        let rows = match menu.mode {
+            ShopMode::Buy => shop.stock.len(),
+            ShopMode::Sell => inventory.stacks().count(),
+        };
+        menu.dirty = true;
+
+        match action {
+            ShopAction::Move(direction) => {
+                let moved = menu.cursor as i32 + direction;
+                menu.cursor = moved.clamp(0, rows.saturating_sub(1) as i32) as usize;
+            }
+            ShopAction::SwitchMode => {
+                menu.mode = match menu.mode {
+                    ShopMode::Buy => ShopMode::Sell,
+                    ShopMode::Sell => ShopMode::Buy,
+                };
+                menu.cursor = 0;
+            }
+            ShopAction::Confirm => {
+                menu.message = match menu.mode {
+                    ShopMode::Buy => {
+                        match shop.buy(menu.cursor, &mut wallet, &mut inventory, &item_registry) {
+                            Ok(id) => format!("Bought {}", item_name(&item_registry, &id)),
+                            Err(err) => format!("Can't buy that: {:?}", err),
+                        }
+                    }
+                    ShopMode::Sell => {
+                        let slot = inventory.stacks().nth(menu.cursor).map(|(slot, _)| slot);
+                        let result = slot.ok_or(ShopError::EmptySlot).and_then(|slot| {
+                            shop.sell(slot, &mut wallet, &mut inventory, &item_registry)
+                        });
+                        match result {
+                            Ok((id, price)) => format!(
+                                "Sold {} for {} coins",
+                                item_name(&item_registry, &id),
+                                price
+                            ),
+                            Err(err) => format!("Can't sell that: {:?}", err),
+                        }
+                    }
+                };
+            }
+            ShopAction::Close => {
+                close_menu(&mut input, &mut gamestate);
+                return;
+            }
+        }
+    }
+}
+
+/// Rebuild the ui of the shop menu when it changed
+pub(crate) fn draw_shop_menu(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    item_registry: Res<ItemRegistry>,
+    mut menu: ResMut<ShopMenuState>,
+    shops: Query<&Shop>,
+    customers: Query<(&Wallet, &Inventory)>,
+    ui: Query<Entity, With<ShopMenuUi>>,
+) {
+    if !menu.dirty {
+        return;
+    }
+    let (shop, (wallet, inventory)) = match menu
+        .shop
+        .zip(menu.customer)
+        .and_then(|(shop, customer)| shops.get(shop).ok().zip(customers.get(customer).ok()))
+    {
+        Some(open) => open,
+        None => return,
+    };
+    menu.dirty = false;
+    ui.for_each(|entity| commands.entity(entity).despawn_recursive());
+
+    // The name, price and quantity of each row
+    let rows = match menu.mode {
+        ShopMode::Buy => shop
+            .stock
+            .iter()
+            .map(|stock| {
+                let quantity = match stock.quantity {
+                    Some(0) => "sold out".to_string(),
+                    Some(quantity) => format!("x{}", quantity),
+                    None => String::new(),
+                };
+                (
+                    item_name(&item_registry, &stock.item).to_string(),
+                    format!("{} coins", stock.price),
+                    quantity,
+                )
+            })
+            .collect::<Vec<_>>(),
+        ShopMode::Sell => inventory
+            .stacks()
+            .map(|(_, stack)| {
+                let price = item_registry
+                    .get(&stack.id)
+                    .and_then(|item| item.sell_price)
+                    .map_or("can't sell".to_string(), |price| format!("{} coins", price));
+                (
+                    item_name(&item_registry, &stack.id).to_string(),
+                    price,
+                    format!("x{}", stack.count),
+                )
+            })
+            .collect::<Vec<_>>(),
+    };
+    menu.cursor = menu.cursor.min(rows.len().saturating_sub(1));
+
+    let text = |value: &str, size: f32, color: Color| TextBundle {
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: fonts.fira_sans.clone(),
+                font_size: size,
+                color,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(ShopMenuUi)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        size: Size::new(Val::Px(420.0), Val::Auto),
+                        padding: Rect::all(Val::Px(16.0)),
+                        ..Default::default()
+                    },
+                    color: Color::BLACK.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(text(&shop.name, 40.0, Color::WHITE));
+                    parent.spawn_bundle(text(
+                        &format!("{:?}    Coins: {}", menu.mode, wallet.coins),
+                        20.0,
+                        Color::YELLOW,
+                    ));
+
+                    // A row for each item
+                    for (index, (name, price, quantity)) in rows.iter().enumerate() {
+                        let color = if index == menu.cursor {
+                            Color::YELLOW
+                        } else {
+                            Color::WHITE
+                        };
+                        parent.spawn_bundle(text(
+                            &format!("{}  {}  {}", name, price, quantity),
+                            20.0,
+                            color,
+                        ));
+                    }
+                    if rows.is_empty() {
+                        parent.spawn_bundle(text("Nothing here", 20.0, Color::GRAY));
+                    }
+
+                    // The result of the last purchase or sale
+                    if !menu.message.is_empty() {
+                        parent.spawn_bundle(text(&menu.message, 16.0, Color::YELLOW));
+                    }
+
+                    parent.spawn_bundle(text(
+                        "Enter buy/sell, A/D switch, Esc close",
+                        14.0,
+                        Color::GRAY,
+                    ));
+                });
+        });
+}
+
+/// Restock the shops over time
+pub(crate) fn restock_shops(time: Res<SimulationTime>, mut shops: Query<&mut Shop>) {
+    shops.for_each_mut(|mut shop| {
+        for stock in &mut shop.stock {
+            let (quantity, restock_time) = match (stock.quantity, stock.restock_time) {
+                (Some(quantity), Some(restock_time)) => (quantity, restock_time),
+                _ => continue,
+            };
+            if quantity >= stock.max_quantity {
+                stock.restock_timer = 0.0;
+                continue;
+            }
+            stock.restock_timer += time.delta_seconds();
+            if stock.restock_timer >= restock_time {
+                stock.restock_timer -= restock_time;
+                stock.quantity = Some(quantity + 1);
+            }
+        }
+    });
+}
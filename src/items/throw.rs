@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+
+use super::{inventory::Inventory, SpawnItemEvent};
+use crate::{
+    combat::Facing,
+    health::{DamageEvent, Health},
+    items::equipment::StatModifiers,
+    levels::{Switch, SwitchToggledEvent},
+    physics::component::{CollidingEntities, Velocity},
+    player::component::Player,
+    simulation::SimulationTime,
+};
+
+/// The config for dropping and throwing items
+#[derive(Debug, Clone)]
+pub struct ThrowConfig {
+    // The velocity of a dropped item, the x axis points in the facing direction
+    pub drop_velocity: Vec2,
+    // The velocity of a thrown item, the x axis points in the facing direction
+    pub throw_velocity: Vec2,
+    // The time in seconds before the dropper can pick the item up again
+    pub pickup_cooldown: f32,
+    // The damage a thrown item deals, before the thrower's damage bonus
+    pub damage: u32,
+    // The speed below which a thrown item stops hitting things
+    pub min_hit_speed: f32,
+}
+
+impl Default for ThrowConfig {
+    fn default() -> Self {
+        Self {
+            drop_velocity: Vec2::new(30.0, 40.0),
+            throw_velocity: Vec2::new(200.0, 60.0),
+            pickup_cooldown: 1.0,
+            damage: 1,
+            min_hit_speed: 40.0,
+        }
+    }
+}
+
+/// An event that drops or throws units of the item in an inventory slot
+#[derive(Debug, Clone)]
+pub struct DropItemEvent {
+    pub entity: Entity,
+    pub slot: usize,
+    pub count: u32,
+    pub throw: bool,
+}
+
+/// Stops an entity from picking up an item it just dropped
+#[derive(Debug, Clone, Component)]
+pub struct PickupCooldown {
+    pub ignored: Entity,
+    // The time left in seconds
+    pub remaining: f32,
+}
+
+/// Marks an item that was thrown and still hits what it touches
+#[derive(Debug, Clone, Component)]
+pub struct Thrown {
+    pub by: Entity,
+    pub damage: u32,
+}
+
+/// Remove the dropped items from the inventories and spawn them in front of the dropper
+pub(crate) fn handle_drops(
+    config: Res<ThrowConfig>,
+    mut holders: Query<(
+        &mut Inventory,
+        &Transform,
+        Option<&Facing>,
+        Option<&StatModifiers>,
+    )>,
+    mut drop_events: EventReader<DropItemEvent>,
+    mut spawn_item_events: EventWriter<SpawnItemEvent>,
+) {
+    for event in drop_events.iter() {
+        let (mut inventory, transform, facing, modifiers) = match holders.get_mut(event.entity) {
+            Ok(holder) => holder,
+            Err(_) => continue,
+        };
+        let dropped = match inventory.remove_item(event.slot, event.count) {
+            Some(dropped) => dropped,
+            None => continue,
+        };
+
+        let facing = facing.copied().unwrap_or_default().sign();
+        let velocity = if event.throw {
+            config.throw_velocity
+        } else {
+            config.drop_velocity
+        };
+
+        for _ in 0..dropped.count {
+            spawn_item_events.send(SpawnItemEvent {
+                item: dropped.id.clone(),
+                position: transform.translation.truncate() + Vec2::new(0.0, 4.0),
+                velocity: Vec2::new(velocity.x * facing, velocity.y),
+                dropped_by: Some(event.entity),
+                thrown: event.throw.then(|| Thrown {
+                    by: event.entity,
+                    damage: config.damage + modifiers.map_or(0, |modifiers| modifiers.damage),
+                }),
+            });
+        }
+    }
+}
+
+/// Count down the pickup cooldowns
+pub(crate) fn tick_pickup_cooldowns(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut cooldowns: Query<(Entity, &mut PickupCooldown)>,
+) {
+    cooldowns.for_each_mut(|(entity, mut cooldown)| {
+        cooldown.remaining -= time.delta_seconds();
+        if cooldown.remaining <= 0.0 {
+            commands.entity(entity).remove::<PickupCooldown>();
+        }
+    });
+}
+
+/// Let the thrown items hit the switches and enemies they touch
+pub(crate) fn handle_thrown_hits(
+    mut commands: Commands,
+    config: Res<ThrowConfig>,
+    thrown: Query<(Entity, &Thrown, &Velocity, &CollidingEntities)>,
+    mut switches: Query<&mut Switch>,
+    targets: Query<(), (With<Health>, Without<Player>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut toggled_events: EventWriter<SwitchToggledEvent>,
+) {
+    thrown.for_each(|(entity, thrown, velocity, colliding_entities)| {
+        // Slow items are just lying around
+        if velocity.length() < config.min_hit_speed {
+            commands.entity(entity).remove::<Thrown>();
+            return;
+        }
+
+        let mut hit = false;
+        for other in colliding_entities.iter() {
+            if *other == thrown.by {
+                continue;
+            }
+            if let Ok(mut switch) = switches.get_mut(*other) {
+                switch.on = !switch.on;
+                toggled_events.send(SwitchToggledEvent {
+                    switch: *other,
+                    on: switch.on,
+                });
+                hit = true;
+            }
+            if targets.get(*other).is_ok() {
+                damage_events.send(DamageEvent {
+                    target: *other,
+                    source: Some(entity),
+                    amount: thrown.damage,
+                    knockback: Vec2::new(velocity.x.signum() * 80.0, 60.0),
+                });
+                hit = true;
+            }
+        }
+        // An item only hits once
+        if hit {
+            commands.entity(entity).remove::<Thrown>();
+        }
+    });
+}
@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+
+use super::respawn::{RespawnEvent, RespawnSequence, SpawnPoint};
+use crate::prelude::{CollidingEntities, Player, SaveEvent};
+
+/// A checkpoint that becomes the spawn point of the players that touch it
+#[derive(Debug, Default, Clone, Component)]
+pub struct Checkpoint {
+    // The position the players respawn at
+    pub spawn_position: Vec2,
+    // Whether a player already touched the checkpoint
+    pub activated: bool,
+}
+
+/// The area a level takes up, players leaving it respawn
+#[derive(Debug, Clone, Component)]
+pub struct LevelBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl LevelBounds {
+    /// Check if a position is inside the bounds
+    pub fn contains(&self, position: Vec2) -> bool {
+        position.x >= self.min.x
+            && position.x <= self.max.x
+            && position.y >= self.min.y
+            && position.y <= self.max.y
+    }
+}
+
+/// Activate the checkpoints the players touch
+pub(crate) fn activate_checkpoints(
+    mut checkpoints: Query<(&mut Checkpoint, Option<&mut Sprite>)>,
+    mut players: Query<(&CollidingEntities, &mut SpawnPoint), With<Player>>,
+    mut save_events: EventWriter<SaveEvent>,
+) {
+    // Find the checkpoint that was touched this frame
+    let mut touched = None;
+    players.for_each(|(colliding_entities, _)| {
+        for entity in colliding_entities.iter() {
+            if checkpoints.get(*entity).is_ok() {
+                touched = Some(*entity);
+            }
+        }
+    });
+
+    if let Some(touched) = touched {
+        let spawn_position = {
+            let (mut checkpoint, sprite) = checkpoints.get_mut(touched).unwrap();
+            if !checkpoint.activated {
+                info!("Checkpoint reached");
+                checkpoint.activated = true;
+                save_events.send(SaveEvent);
+                if let Some(mut sprite) = sprite {
+                    sprite.color = Color::rgba_u8(0, 255, 0, 128);
+                }
+            }
+            checkpoint.spawn_position
+        };
+
+        // All the players respawn at the last checkpoint
+        players.for_each_mut(|(_, mut spawn_point)| {
+            spawn_point.0 = spawn_position;
+        });
+    }
+}
+
+/// Respawn the players that left the level bounds
+pub(crate) fn check_level_bounds(
+    sequence: Res<RespawnSequence>,
+    bounds: Query<&LevelBounds>,
+    players: Query<(Entity, &Transform), With<Player>>,
+    mut respawn_events: EventWriter<RespawnEvent>,
+) {
+    players.for_each(|(entity, transform)| {
+        if sequence.pending.contains(&entity) {
+            return;
+        }
+        let position = transform.translation.truncate();
+        let has_bounds = bounds.iter().next().is_some();
+        let inside = bounds.iter().any(|bounds| bounds.contains(position));
+        if has_bounds && !inside {
+            respawn_events.send(RespawnEvent(entity));
+        }
+    });
+}
@@ -0,0 +1,25 @@
+use bevy::prelude::*;
+
+/// A chest that is opened by touching it and pressing the interact button
+#[derive(Debug, Default, Clone, Component)]
+pub struct Chest {
+    pub opened: bool,
+}
+
+/// An event that is sent when a chest is opened
+#[derive(Debug, Clone)]
+pub struct ChestOpenedEvent {
+    pub chest: Entity,
+    pub opened_by: Entity,
+}
+
+/// Darken the chests that were opened
+pub(crate) fn update_chest_sprites(mut chests: Query<(&Chest, &mut Sprite), Changed<Chest>>) {
+    chests.for_each_mut(|(chest, mut sprite)| {
+        sprite.color = if chest.opened {
+            Color::rgba_u8(96, 64, 0, 200)
+        } else {
+            Color::rgba_u8(200, 150, 0, 200)
+        };
+    });
+}
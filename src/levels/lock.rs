@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+
+use crate::{
+    items::{effect::ItemUsedEvent, inventory::Inventory, ItemId, ItemRegistry},
+    physics::component::{Collider, CollidingEntities},
+    player::component::{Player, PlayerState},
+    save::SaveData,
+    simulation::SimulationTime,
+};
+
+/// The time in seconds the unlock sequence takes
+const UNLOCK_DURATION: f32 = 0.6;
+
+/// The state of a lock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockState {
+    Locked,
+    // The lock is playing its unlock sequence
+    Unlocking { elapsed: f32 },
+    Open,
+}
+
+impl Default for LockState {
+    fn default() -> Self {
+        Self::Locked
+    }
+}
+
+/// A door, gate or chest that needs a key item to open
+/// The lock is solid until it is opened
+#[derive(Debug, Clone, Component)]
+pub struct Lock {
+    // The id of the key item
+    pub key: ItemId,
+    // Whether the key is used up by opening the lock
+    pub consume_key: bool,
+    // The world flag that remembers the lock was opened
+    pub flag: String,
+    pub state: LockState,
+}
+
+impl Lock {
+    /// Creates a new [`Lock`] that is locked
+    pub fn new(key: &str, consume_key: bool, flag: &str) -> Self {
+        Self {
+            key: key.into(),
+            consume_key,
+            flag: flag.into(),
+            state: LockState::Locked,
+        }
+    }
+
+    /// Check if the lock is open
+    pub fn is_open(&self) -> bool {
+        self.state == LockState::Open
+    }
+}
+
+/// Marks a lock that blocks the way and disappears once it is opened, like a door or a gate
+#[derive(Debug, Default, Clone, Component)]
+pub struct Door;
+
+/// An event that is sent when a lock is done opening
+#[derive(Debug, Clone)]
+pub struct LockOpenedEvent {
+    pub lock: Entity,
+    pub opened_by: Option<Entity>,
+}
+
+/// The player that started the unlock sequence
+#[derive(Debug, Clone, Component)]
+pub struct UnlockedBy(pub Entity);
+
+/// Open the locks whose world flag is already set, so they stay open across level reloads
+pub(crate) fn restore_opened_locks(
+    save_data: Res<SaveData>,
+    mut locks: Query<
+        (
+            &mut Lock,
+            &mut Collider,
+            Option<&mut Visibility>,
+            Option<&Door>,
+        ),
+        Added<Lock>,
+    >,
+) {
+    locks.for_each_mut(|(mut lock, mut collider, visibility, door)| {
+        if save_data.flag(&lock.flag) {
+            lock.state = LockState::Open;
+            open(&mut collider, visibility, door);
+        }
+    });
+}
+
+/// Start unlocking the locks the players touch and press the interact button at
+pub(crate) fn unlock_locks(
+    mut commands: Commands,
+    item_registry: Res<ItemRegistry>,
+    mut save_data: ResMut<SaveData>,
+    mut players: Query<(Entity, &PlayerState, &CollidingEntities, &mut Inventory), With<Player>>,
+    mut locks: Query<&mut Lock>,
+    mut used_events: EventWriter<ItemUsedEvent>,
+) {
+    players.for_each_mut(|(player, state, colliding_entities, mut inventory)| {
+        if !state.interact_pressed {
+            return;
+        }
+        for entity in colliding_entities.iter() {
+            let mut lock = match locks.get_mut(*entity) {
+                Ok(lock) => lock,
+                Err(_) => continue,
+            };
+            if lock.state != LockState::Locked {
+                continue;
+            }
+
+            let key_name = item_registry
+                .get(&lock.key)
+                .map_or(lock.key.as_str(), |item| item.name.as_str());
+            let slot = inventory
+                .stacks()
+                .find(|(_, stack)| stack.id == lock.key)
+                .map(|(slot, _)| slot);
+            let slot = match slot {
+                Some(slot) => slot,
+                None => {
+                    used_events.send(ItemUsedEvent {
+                        user: player,
+                        item: lock.key.clone(),
+                        success: false,
+                        message: format!("It's locked, it needs the {}", key_name),
+                    });
+                    continue;
+                }
+            };
+
+            let message = if lock.consume_key {
+                inventory.remove_item(slot, 1);
+                format!("Used up the {}", key_name)
+            } else {
+                format!("Unlocked with the {}", key_name)
+            };
+            used_events.send(ItemUsedEvent {
+                user: player,
+                item: lock.key.clone(),
+                success: true,
+                message,
+            });
+
+            // Set the flag right away so saving during the sequence keeps the lock open
+            save_data.set_flag(&lock.flag);
+            lock.state = LockState::Unlocking { elapsed: 0.0 };
+            commands.entity(*entity).insert(UnlockedBy(player));
+        }
+    });
+}
+
+/// Shake and fade the locks that are unlocking, then open them
+pub(crate) fn run_unlock_sequences(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut locks: Query<(
+        Entity,
+        &mut Lock,
+        &mut Collider,
+        Option<&mut Sprite>,
+        Option<&mut Visibility>,
+        Option<&Door>,
+        Option<&UnlockedBy>,
+    )>,
+    mut opened_events: EventWriter<LockOpenedEvent>,
+) {
+    locks.for_each_mut(
+        |(entity, mut lock, mut collider, sprite, visibility, door, unlocked_by)| {
+            let elapsed = match lock.state {
+                LockState::Unlocking { elapsed } => elapsed + time.delta_seconds(),
+                _ => return,
+            };
+            let progress = (elapsed / UNLOCK_DURATION).min(1.0);
+
+            // Doors fade out while they unlock, everything else flashes
+            if let Some(mut sprite) = sprite {
+                let alpha = if door.is_some() {
+                    1.0 - progress
+                } else if progress >= 1.0 {
+                    1.0
+                } else {
+                    0.6 + 0.4 * (elapsed * 30.0).sin().abs()
+                };
+                sprite.color.set_a(alpha);
+            }
+
+            if progress < 1.0 {
+                lock.state = LockState::Unlocking { elapsed };
+                return;
+            }
+            lock.state = LockState::Open;
+            open(&mut collider, visibility, door);
+            commands.entity(entity).remove::<UnlockedBy>();
+            opened_events.send(LockOpenedEvent {
+                lock: entity,
+                opened_by: unlocked_by.map(|unlocked_by| unlocked_by.0),
+            });
+        },
+    );
+}
+
+/// Make an opened lock passable, and hide it if it is a door
+fn open(collider: &mut Collider, visibility: Option<Mut<Visibility>>, door: Option<&Door>) {
+    collider.tags.retain(|tag| tag != "ground");
+    if let (Some(mut visibility), Some(_)) = (visibility, door) {
+        visibility.is_visible = false;
+    }
+}
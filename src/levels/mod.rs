@@ -1,10 +1,32 @@
 use std::path::PathBuf;
 use bevy::prelude::*;
 
+use self::{
+    checkpoint::{activate_checkpoints, check_level_bounds},
+    chest::update_chest_sprites,
+    lock::{restore_opened_locks, run_unlock_sequences, unlock_locks},
+    respawn::{record_reset_origins, run_respawn_sequence, start_respawns},
+    switch::update_switch_sprites,
+};
+pub use self::{
+    checkpoint::{Checkpoint, LevelBounds},
+    chest::{Chest, ChestOpenedEvent},
+    lock::{Door, Lock, LockOpenedEvent, LockState},
+    switch::{Switch, SwitchToggledEvent},
+    respawn::{ResetOnRespawn, RespawnEvent, RespawnPhase, RespawnSequence, SpawnPoint},
+};
+pub mod checkpoint;
+pub mod chest;
+pub mod lock;
+pub mod respawn;
+pub mod switch;
+
 use crate::prelude::{
-    CameraTarget, Collider, CollidingDirections, CollidingEntities,
-    GravityScale, Inventory, PhysicsBody, PhysicsBodyBundle, PlayerBundle,
-    PlayerMovementStats, SpawnItemEvent,
+    ActiveView, CameraTarget, Collider, CollidingDirections, CollidingEntities, Container,
+    Dimensional, GameState, Hazard, Health, Hurtbox, Inventory, ItemMagnet, ItemRegistry,
+    LootDrop, PhysicsBody, PhysicsBodyBundle, PlayerBundle, PlayerInputState,
+    PlayerMovementStats, PlayerSlot, PlayerSlots, PlayerState, Secret, Shop, ShopStock,
+    SpawnItemEvent, View, player_animator,
 };
 
 /// The marker component for a level object
@@ -24,8 +46,39 @@ impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnLevelEvent>();
         app.add_event::<DestroyLevelsEvent>();
-        app.add_system(spawn_level_event);
-        app.add_system(destroy_levels_event);
+        app.add_event::<RespawnEvent>();
+        app.add_event::<LockOpenedEvent>();
+        app.add_event::<SwitchToggledEvent>();
+        app.add_event::<ChestOpenedEvent>();
+        app.add_system(update_switch_sprites);
+        app.add_system(update_chest_sprites);
+        app.add_system(restore_opened_locks);
+        app.init_resource::<RespawnSequence>();
+        app.add_system(destroy_levels_event.label("destroy_levels"));
+        app.add_system(spawn_level_event.label("spawn_level").after("destroy_levels"));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(activate_checkpoints.after("handle_collision"))
+                .with_system(
+                    unlock_locks
+                        .label("unlock_locks")
+                        .after("handle_collision")
+                        .after("read_interactions"),
+                )
+                .with_system(run_unlock_sequences.after("unlock_locks"))
+                .with_system(
+                    check_level_bounds
+                        .after("apply_velocity")
+                        .before("start_respawns"),
+                ),
+        );
+        app.add_system(record_reset_origins);
+        app.add_system(start_respawns.label("start_respawns"));
+        app.add_system(
+            run_respawn_sequence
+                .label("run_respawn_sequence")
+                .after("start_respawns"),
+        );
     }
 }
 
@@ -35,44 +88,87 @@ fn spawn_level_event(
     mut camera_targets: Query<&mut CameraTarget>,
     mut spawn_level_events: EventReader<SpawnLevelEvent>,
     mut spawn_item_events: EventWriter<SpawnItemEvent>,
+    player_slots: Res<PlayerSlots>,
+    item_registry: Res<ItemRegistry>,
+    mut active_view: ResMut<ActiveView>,
     asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
 ) {
     for _ in spawn_level_events.iter() {
-        // Spawn the player
-        let player = commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("sprites/player.png"),
-                ..Default::default()
-            })
-            .insert_bundle(PhysicsBodyBundle {
-                gravity_scale: GravityScale(2.0),
-                ..Default::default()
-            })
-            .insert(Collider {
-                tags: vec!["player".into()],
-                colliding_tags: vec!["ground".into()],
-                half_extents: Vec2::new(5.0, 10.0),
-            })
-            .insert(CollidingDirections::default())
-            .insert(CollidingEntities::default())
-            .insert_bundle(PlayerBundle {
-                player_movement_stats: PlayerMovementStats {
-                    walking_accel: 325.0,
-                    sprint_accel: 450.0,
-                    walking_friction: 6.4,
-                    jump_force: 1250.0,
-                    jump_time: 0.08,
+        // Levels always start in the front view
+        active_view.view = View::Front;
+
+        // The player sprite sheet, each row of the sheet holds a clip
+        let player_atlas = texture_atlases.add(TextureAtlas::from_grid(
+            asset_server.load("sprites/player.png"),
+            Vec2::new(16.0, 24.0),
+            8,
+            10,
+        ));
+
+        // Spawn a player for each joined slot
+        let mut players = vec![];
+        for (slot, controller) in player_slots.joined() {
+            let player = commands
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: player_atlas.clone(),
                     ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(Inventory::with_capacity(10))
-            .insert(LevelObject)
-            .id();
-        
-        // Assign the player to the camera target
+                })
+                .insert_bundle(PhysicsBodyBundle {
+                    transform: Transform::from_xyz(slot as f32 * 16.0, 0.0, 0.0),
+                    ..Default::default()
+                })
+                .insert(Collider {
+                    tags: vec!["player".into()],
+                    colliding_tags: vec!["ground".into()],
+                    half_extents: Vec2::new(5.0, 10.0),
+                })
+                .insert(CollidingDirections::default())
+                .insert(CollidingEntities::default())
+                .insert_bundle(PlayerBundle {
+                    player_slot: PlayerSlot(slot),
+                    player_state: PlayerState {
+                        input: PlayerInputState {
+                            controller,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    player_movement_stats: PlayerMovementStats {
+                        walking_accel: 325.0,
+                        sprint_accel: 450.0,
+                        walking_friction: 6.4,
+                        min_jump_height: 12.0,
+                        max_jump_height: 48.0,
+                        time_to_apex: 0.4,
+                        fall_gravity_multiplier: 1.6,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(player_animator())
+                .insert(Health::new(5, 1.0))
+                .insert(Hurtbox {
+                    half_extents: Vec2::new(5.0, 10.0),
+                })
+                .insert(SpawnPoint(Vec2::new(slot as f32 * 16.0, 0.0)))
+                .insert(Dimensional::both(
+                    Vec2::new(slot as f32 * 16.0, 0.0),
+                    Vec2::new(slot as f32 * 16.0, 0.0),
+                ))
+                .insert(Inventory::with_capacity(10))
+                .insert(ItemMagnet {
+                    radius: 24.0,
+                    speed: 120.0,
+                })
+                .insert(LevelObject)
+                .id();
+            players.push(player);
+        }
+
+        // Assign the first player to the camera target
         camera_targets.for_each_mut(|mut target| {
-            target.0 = Some(player);
+            target.0 = players.first().copied();
         });
 
         // TODO: Replace the test level with a loaded level
@@ -118,30 +214,325 @@ fn spawn_level_event(
             .insert(Transform::from_xyz(-164.0, -48.0, 0.0))
             .insert(LevelObject);
 
+        // A platform that only exists in the front view
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(0, 255, 0, 128),
+                    custom_size: Vec2::new(48.0, 8.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["ground".into()],
+                colliding_tags: vec!["ground".into()],
+                half_extents: Vec2::new(24.0, 4.0),
+            })
+            .insert(Transform::from_xyz(112.0, -24.0, 0.0))
+            .insert(Dimensional::front(Vec2::new(112.0, -24.0)))
+            .insert(LevelObject);
+
+        // A platform that only exists in the side view
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(0, 255, 255, 128),
+                    custom_size: Vec2::new(48.0, 8.0).into(),
+                    ..Default::default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["ground".into()],
+                colliding_tags: vec!["ground".into()],
+                half_extents: Vec2::new(24.0, 4.0),
+            })
+            .insert(Transform::from_xyz(48.0, 8.0, 0.0))
+            .insert(Dimensional::side(Vec2::new(48.0, 8.0)))
+            .insert(LevelObject);
+
+        // A spike pit that hurts the player
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(255, 128, 0, 128),
+                    custom_size: Vec2::new(32.0, 8.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["ground".into(), "hazard".into()],
+                colliding_tags: vec!["ground".into()],
+                half_extents: Vec2::new(16.0, 4.0),
+            })
+            .insert(Hazard {
+                damage: 1,
+                knockback: 160.0,
+            })
+            .insert(Transform::from_xyz(-96.0, -52.0, 0.0))
+            .insert(ResetOnRespawn::default())
+            .insert(LevelObject);
+
+        // A checkpoint on the left platform
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(0, 0, 255, 128),
+                    custom_size: Vec2::new(8.0, 16.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["checkpoint".into()],
+                colliding_tags: vec![],
+                half_extents: Vec2::new(4.0, 8.0),
+            })
+            .insert(Checkpoint {
+                spawn_position: Vec2::new(-180.0, -20.0),
+                activated: false,
+            })
+            .insert(Transform::from_xyz(-180.0, -32.0, 0.0))
+            .insert(LevelObject);
+
+        // A hidden platform that only shows up through the monocle
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(255, 255, 255, 96),
+                    custom_size: Vec2::new(32.0, 8.0).into(),
+                    ..Default::default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["ground".into()],
+                colliding_tags: vec!["ground".into()],
+                half_extents: Vec2::new(16.0, 4.0),
+            })
+            .insert(Transform::from_xyz(-96.0, 0.0, 0.0))
+            .insert(Secret)
+            .insert(LevelObject);
+
+        // A locked door at the right end of the ground
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(128, 64, 0, 200),
+                    custom_size: Vec2::new(8.0, 32.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["ground".into(), "door".into()],
+                colliding_tags: vec!["ground".into()],
+                half_extents: Vec2::new(4.0, 16.0),
+            })
+            .insert(Lock::new("rusty_key", true, "test_level.rusty_door"))
+            .insert(Door)
+            .insert(Transform::from_xyz(60.0, -40.0, 0.0))
+            .insert(LevelObject);
+
+        // A switch that can be hit by throwing an item at it
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(255, 0, 0, 200),
+                    custom_size: Vec2::new(8.0, 8.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["switch".into()],
+                colliding_tags: vec![],
+                half_extents: Vec2::new(4.0, 4.0),
+            })
+            .insert(Switch::default())
+            .insert(Transform::from_xyz(-40.0, -20.0, 0.0))
+            .insert(LevelObject);
+
+        // Pots that break when something is thrown at them
+        for x in [32.0, 48.0] {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba_u8(180, 90, 40, 200),
+                        custom_size: Vec2::new(10.0, 12.0).into(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert_bundle(PhysicsBodyBundle {
+                    body: PhysicsBody::Static,
+                    ..Default::default()
+                })
+                .insert(Collider {
+                    tags: vec!["pot".into()],
+                    colliding_tags: vec![],
+                    half_extents: Vec2::new(5.0, 6.0),
+                })
+                .insert(Health::new(1, 0.0))
+                .insert(Hurtbox {
+                    half_extents: Vec2::new(5.0, 6.0),
+                })
+                .insert(LootDrop("pot".into()))
+                .insert(Transform::from_xyz(x, -50.0, 0.0))
+                .insert(LevelObject);
+        }
+
+        // A chest that drops its loot when opened
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(200, 150, 0, 200),
+                    custom_size: Vec2::new(16.0, 12.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["chest".into()],
+                colliding_tags: vec![],
+                half_extents: Vec2::new(8.0, 6.0),
+            })
+            .insert(Chest::default())
+            .insert(LootDrop("chest".into()))
+            .insert(Transform::from_xyz(0.0, -50.0, 0.0))
+            .insert(LevelObject);
+
+        // A shopkeeper on the left platform
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(160, 0, 200, 200),
+                    custom_size: Vec2::new(12.0, 20.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["npc".into()],
+                colliding_tags: vec![],
+                half_extents: Vec2::new(6.0, 10.0),
+            })
+            .insert(Shop {
+                name: "Bean Merchant".into(),
+                stock: vec![
+                    ShopStock::unlimited("bean", 2),
+                    ShopStock::limited("pepper", 6, 2).restocking(20.0),
+                    ShopStock::limited("monocle", 15, 1),
+                    ShopStock::limited("slingshot", 12, 1),
+                ],
+            })
+            .insert(Transform::from_xyz(-140.0, -30.0, 0.0))
+            .insert(LevelObject);
+
+        // A stash with a few supplies in it
+        let mut stash = Inventory::with_capacity(6);
+        for (id, count) in [("bean", 4), ("pepper", 1)] {
+            if let Some(item) = item_registry.get(id) {
+                stash.add_item(item, count);
+            }
+        }
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba_u8(120, 80, 40, 200),
+                    custom_size: Vec2::new(16.0, 10.0).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                body: PhysicsBody::Static,
+                ..Default::default()
+            })
+            .insert(Collider {
+                tags: vec!["container".into()],
+                colliding_tags: vec![],
+                half_extents: Vec2::new(8.0, 5.0),
+            })
+            .insert(Container {
+                name: "Stash".into(),
+            })
+            .insert(stash)
+            .insert(Transform::from_xyz(-24.0, -51.0, 0.0))
+            .insert(LevelObject);
+
+        // The kill plane below the level
+        commands
+            .spawn()
+            .insert(LevelBounds {
+                min: Vec2::new(-512.0, -256.0),
+                max: Vec2::new(512.0, 512.0),
+            })
+            .insert(LevelObject);
+
         spawn_item_events.send(SpawnItemEvent {
             item: "mirror".into(),
             position: Vec2::new(-64.0, 20.0),
             velocity: Vec2::new(0.0, 0.0),
+            ..Default::default()
         });
         spawn_item_events.send(SpawnItemEvent {
             item: "monocle".into(),
             position: Vec2::new(-32.0, 20.0),
             velocity: Vec2::new(-0.0, 0.0),
+            ..Default::default()
         });
         spawn_item_events.send(SpawnItemEvent {
-            item: "bean".into(),
-            position: Vec2::new(64.0, 20.0),
-            velocity: Vec2::new(0.0, 0.0),
-        });
-        spawn_item_events.send(SpawnItemEvent {
-            item: "bean".into(),
-            position: Vec2::new(48.0, 20.0),
+            item: "rusty_key".into(),
+            position: Vec2::new(-140.0, 0.0),
             velocity: Vec2::new(0.0, 0.0),
+            ..Default::default()
         });
         spawn_item_events.send(SpawnItemEvent {
-            item: "bean".into(),
-            position: Vec2::new(32.0, 20.0),
+            item: "feather".into(),
+            position: Vec2::new(-180.0, 0.0),
             velocity: Vec2::new(0.0, 0.0),
+            ..Default::default()
         });
     }
 }
@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+
+use crate::prelude::{
+    Collider, Dimensional, Health, Invulnerable, PlayerMovementStats, PlayerState, ScreenFade,
+    Velocity,
+};
+
+/// The position an entity returns to when it respawns
+#[derive(Debug, Default, Clone, Component)]
+pub struct SpawnPoint(pub Vec2);
+
+/// An event that respawns an entity at its spawn point
+#[derive(Debug, Clone)]
+pub struct RespawnEvent(pub Entity);
+
+/// Marks a level object that returns to its initial state when a player respawns
+/// Used for the hazards and enemies of a room
+#[derive(Debug, Default, Clone, Component)]
+pub struct ResetOnRespawn {
+    // The transform the object had when it was spawned
+    pub origin: Option<Transform>,
+}
+
+/// The phase of the respawn sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RespawnPhase {
+    Idle,
+    FadingOut,
+    // The screen is black and the players and the room are reset this frame
+    Resetting,
+    FadingIn,
+}
+
+impl Default for RespawnPhase {
+    fn default() -> Self {
+        Self::Idle
+    }
+}
+
+/// The respawn sequence that is currently running
+#[derive(Debug, Default, Clone)]
+pub struct RespawnSequence {
+    pub phase: RespawnPhase,
+    // The entities that will be respawned once the screen is black
+    pub pending: Vec<Entity>,
+}
+
+/// Queue the respawned entities and start fading out
+pub(crate) fn start_respawns(
+    mut sequence: ResMut<RespawnSequence>,
+    mut fade: ResMut<ScreenFade>,
+    mut respawn_events: EventReader<RespawnEvent>,
+) {
+    for RespawnEvent(entity) in respawn_events.iter() {
+        if !sequence.pending.contains(entity) {
+            sequence.pending.push(*entity);
+        }
+        // Respawns during the fade in start a new fade out
+        if sequence.phase != RespawnPhase::FadingOut {
+            sequence.phase = RespawnPhase::FadingOut;
+            fade.fade_out();
+        }
+    }
+}
+
+/// Runs the respawn sequence: fade out, reset the players and the room, fade in
+pub(crate) fn run_respawn_sequence(
+    mut commands: Commands,
+    mut sequence: ResMut<RespawnSequence>,
+    mut fade: ResMut<ScreenFade>,
+    mut entities: Query<
+        (
+            &SpawnPoint,
+            &mut Transform,
+            Option<&mut Velocity>,
+            Option<&mut Health>,
+            Option<&mut Dimensional>,
+            Option<(&mut PlayerState, &PlayerMovementStats, &mut Collider)>,
+        ),
+        Without<ResetOnRespawn>,
+    >,
+    mut resettables: Query<
+        (
+            &ResetOnRespawn,
+            &mut Transform,
+            Option<&mut Velocity>,
+            Option<&mut Health>,
+        ),
+        Without<SpawnPoint>,
+    >,
+) {
+    match sequence.phase {
+        RespawnPhase::Idle => {}
+        RespawnPhase::FadingOut => {
+            // Reset on the next frame, after the view went back to the front
+            if fade.is_done() {
+                sequence.phase = RespawnPhase::Resetting;
+            }
+        }
+        RespawnPhase::Resetting => {
+            // Move the entities back to their spawn point
+            for entity in sequence.pending.drain(..) {
+                if let Ok((spawn_point, mut transform, velocity, health, dimensional, player)) =
+                    entities.get_mut(entity)
+                {
+                    transform.translation.x = spawn_point.0.x;
+                    transform.translation.y = spawn_point.0.y;
+                    // The view is back to the front, the next flip starts from the spawn point
+                    if let Some(mut dimensional) = dimensional {
+                        *dimensional = Dimensional::both(spawn_point.0, spawn_point.0);
+                    }
+
+                    if let Some(mut velocity) = velocity {
+                        velocity.0 = Vec2::ZERO;
+                    }
+                    if let Some(mut health) = health {
+                        health.current = health.max;
+                    }
+                    // The players come back standing, without the ledge, the dash or the jump
+                    // they died in
+                    if let Some((mut state, stats, mut collider)) = player {
+                        state.hang = None;
+                        state.mantle = None;
+                        state.is_crouched = false;
+                        state.is_sliding = false;
+                        collider.half_extents.y = stats.stand_half_height;
+                        state.dash_time_left = 0.0;
+                        state.jump_active = false;
+                    }
+                    commands.entity(entity).remove::<Invulnerable>();
+                }
+            }
+
+            // Reset the room
+            resettables.for_each_mut(|(reset, mut transform, velocity, health)| {
+                if let Some(origin) = reset.origin {
+                    *transform = origin;
+                }
+                if let Some(mut velocity) = velocity {
+                    velocity.0 = Vec2::ZERO;
+                }
+                if let Some(mut health) = health {
+                    health.current = health.max;
+                }
+            });
+
+            sequence.phase = RespawnPhase::FadingIn;
+            fade.fade_in();
+        }
+        RespawnPhase::FadingIn => {
+            if fade.is_done() {
+                sequence.phase = RespawnPhase::Idle;
+            }
+        }
+    }
+}
+
+/// Remember the initial transform of the objects that reset on respawn
+pub(crate) fn record_reset_origins(
+    mut resettables: Query<(&mut ResetOnRespawn, &Transform), Added<ResetOnRespawn>>,
+) {
+    resettables.for_each_mut(|(mut reset, transform)| {
+        if reset.origin.is_none() {
+            reset.origin = Some(*transform);
+        }
+    });
+}
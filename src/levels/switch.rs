@@ -0,0 +1,25 @@
+use bevy::prelude::*;
+
+/// A switch that is flipped by hitting it
+#[derive(Debug, Default, Clone, Component)]
+pub struct Switch {
+    pub on: bool,
+}
+
+/// An event that is sent when a switch is flipped
+#[derive(Debug, Clone)]
+pub struct SwitchToggledEvent {
+    pub switch: Entity,
+    pub on: bool,
+}
+
+/// Color the switches by their state
+pub(crate) fn update_switch_sprites(mut switches: Query<(&Switch, &mut Sprite), Changed<Switch>>) {
+    switches.for_each_mut(|(switch, mut sprite)| {
+        sprite.color = if switch.on {
+            Color::rgba_u8(0, 255, 0, 200)
+        } else {
+            Color::rgba_u8(255, 0, 0, 200)
+        };
+    });
+}
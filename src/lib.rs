@@ -1,28 +1,63 @@
+pub mod animation;
 pub mod assets;
 pub mod camera;
+pub mod combat;
+pub mod gameover;
 pub mod gameplay;
+pub mod health;
 pub mod items;
 pub mod levels;
+pub mod lobby;
 pub mod mainmenu;
 pub mod physics;
 pub mod player;
+pub mod projectile;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod simulation;
 pub mod startup;
 pub mod state;
 pub mod paused;
 pub mod debug;
+pub mod dimension;
 
 pub mod prelude {
+    pub use crate::animation::*;
     pub use crate::assets::*;
     pub use crate::camera::*;
+    pub use crate::combat::*;
+    pub use crate::dimension::*;
+    pub use crate::gameover::*;
     pub use crate::gameplay::*;
+    pub use crate::health::*;
+    pub use crate::items::container::*;
+    pub use crate::items::crafting::*;
+    pub use crate::items::definition::*;
+    pub use crate::items::effect::*;
+    pub use crate::items::equipment::*;
     pub use crate::items::inventory::*;
+    pub use crate::items::loot::*;
+    pub use crate::items::menu::*;
+    pub use crate::items::pickup::*;
+    pub use crate::items::shop::*;
+    pub use crate::items::throw::*;
     pub use crate::items::*;
     pub use crate::levels::*;
+    pub use crate::lobby::*;
     pub use crate::mainmenu::*;
     pub use crate::physics::component::*;
     pub use crate::physics::*;
+    pub use crate::player::abilities::*;
+    pub use crate::player::buffs::*;
     pub use crate::player::component::*;
+    pub use crate::player::slots::*;
     pub use crate::player::*;
+    pub use crate::projectile::*;
+    pub use crate::replay::*;
+    pub use crate::rng::*;
+    pub use crate::save::*;
+    pub use crate::simulation::*;
     pub use crate::startup::*;
     pub use crate::state::*;
     pub use crate::paused::*;
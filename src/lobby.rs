@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    levels::SpawnLevelEvent,
+    player::{component::ControllerState, slots::PlayerSlots},
+    state::GameState,
+};
+
+/// The plugin that handles the join screen, where each device claims a player slot
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Lobby).with_system(setup));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Lobby)
+                .with_system(join_players.label("join_players"))
+                .with_system(start_game.after("join_players"))
+                .with_system(draw_lobby),
+        );
+    }
+}
+
+/// Setup the lobby state
+fn setup(mut slots: ResMut<PlayerSlots>) {
+    // Release the slots of the previous game
+    slots.clear();
+}
+
+/// Lets the devices claim and release player slots
+fn join_players(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut slots: ResMut<PlayerSlots>,
+    mut gamestate: ResMut<State<GameState>>,
+) {
+    // Join or leave using the keyboard
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        slots.join(ControllerState::Keyboard);
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        slots.leave(ControllerState::Keyboard);
+    }
+
+    // Join or leave using the connected gamepads
+    let gamepads = slots.connected_gamepads.clone();
+    for id in gamepads {
+        let controller = ControllerState::Gamepad(id);
+        if gamepad_input.just_pressed(GamepadButton(Gamepad(id), GamepadButtonType::South)) {
+            slots.join(controller);
+        }
+        if gamepad_input.just_pressed(GamepadButton(Gamepad(id), GamepadButtonType::East)) {
+            slots.leave(controller);
+        }
+    }
+
+    // Release the slots of gamepads that got disconnected
+    let disconnected: Vec<ControllerState> = slots
+        .joined()
+        .map(|(_, controller)| controller)
+        .filter(|controller| !slots.is_connected(*controller))
+        .collect();
+    for controller in disconnected {
+        slots.leave(controller);
+    }
+
+    // Go back to the main menu on escape
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        if *gamestate.current() != GameState::MainMenu {
+            gamestate.set(GameState::MainMenu).unwrap();
+        }
+    }
+}
+
+/// Starts the game once at least one player joined and start is pressed
+fn start_game(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    slots: Res<PlayerSlots>,
+    mut gamestate: ResMut<State<GameState>>,
+    mut spawn_level_events: EventWriter<SpawnLevelEvent>,
+) {
+    if slots.joined().next().is_none() {
+        return;
+    }
+
+    let start_pressed = keyboard_input.just_pressed(KeyCode::Return)
+        || slots.connected_gamepads.iter().any(|id| {
+            gamepad_input.just_pressed(GamepadButton(Gamepad(*id), GamepadButtonType::Start))
+        });
+
+    if start_pressed && *gamestate.current() != GameState::Gameplay {
+        gamestate.set(GameState::Gameplay).unwrap();
+        spawn_level_events.send(SpawnLevelEvent("".into()));
+    }
+}
+
+/// Draws the join screen using egui
+fn draw_lobby(slots: Res<PlayerSlots>, mut egui: ResMut<EguiContext>) {
+    egui::Window::new("Join")
+        .title_bar(true)
+        .anchor(egui::Align2::CENTER_CENTER, &[0.0, 0.0])
+        .fixed_pos(&[0.0, 0.0])
+        .fixed_size(&[300.0, 200.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(egui.ctx_mut(), |ui| {
+            ui.vertical_centered_justified(|ui| {
+                // List the player slots
+                for (index, slot) in slots.slots.iter().enumerate() {
+                    let label = match slot {
+                        Some(ControllerState::Keyboard) => "Keyboard".to_string(),
+                        Some(ControllerState::Gamepad(id)) => format!("Gamepad {}", id),
+                        None => "Press jump to join".to_string(),
+                    };
+                    ui.label(format!("Player {}: {}", index + 1, label));
+                }
+                ui.separator();
+                ui.label("Press start or enter to begin");
+            });
+        });
+}
@@ -1,6 +1,6 @@
 //! A story-based 2d metroidvenia with rpg-elements
 //! Inspired by games like super paper mario and hollow knight
-use bevy::prelude::*;
+use bevy::{asset::AssetServerSettings, prelude::*};
 use island_project::prelude::*;
 
 /// The games entrypoint
@@ -17,6 +17,11 @@ fn main() {
             enabled: true,
             gravity: Vec2::new(0.0, -80.0),
         })
+        .insert_resource(AssetServerSettings {
+            // Reload changed assets, like the item definitions, while the game is running
+            watch_for_changes: true,
+            ..Default::default()
+        })
         .add_plugins(DefaultPlugins)
         .add_plugin(DebugPlugin)
         .add_plugin(AssetsPlugin)
@@ -24,11 +29,21 @@ fn main() {
         .add_plugin(GameStatePlugin)
         .add_plugin(StartupStatePlugin)
         .add_plugin(MainMenuPlugin)
+        .add_plugin(LobbyPlugin)
         .add_plugin(GameplayPlugin)
         .add_plugin(PausedPlugin)
+        .add_plugin(GameOverPlugin)
+        .add_plugin(SimulationPlugin)
+        .add_plugin(ReplayPlugin)
         .add_plugin(PhysicsPlugin)
+        .add_plugin(DimensionPlugin)
         .add_plugin(GameCameraPlugin)
         .add_plugin(PlayerPlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(HealthPlugin)
+        .add_plugin(CombatPlugin)
+        .add_plugin(ProjectilePlugin)
         .add_plugin(ItemPlugin)
+        .add_plugin(SavePlugin)
         .run();
 }
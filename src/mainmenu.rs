@@ -1,7 +1,7 @@
 use bevy::{app::AppExit, prelude::*};
 use bevy_egui::{egui, EguiContext};
 
-use crate::{state::GameState, levels::{SpawnLevelEvent, DestroyLevelsEvent}};
+use crate::{state::GameState, levels::DestroyLevelsEvent};
 
 /// The marker component for ui for the main menu
 #[derive(Debug, Default, Component, Clone)]
@@ -33,7 +33,6 @@ fn setup(mut destroy_level_events: EventWriter<DestroyLevelsEvent>) {
 fn draw_main_menu(
     mut egui: ResMut<EguiContext>,
     mut gamestate: ResMut<State<GameState>>,
-    mut spawn_level_events: EventWriter<SpawnLevelEvent>,
     mut app_exit_events: EventWriter<AppExit>,
 ) {
     egui::Window::new("Project Island")
@@ -47,10 +46,9 @@ fn draw_main_menu(
             ui.vertical_centered_justified(|ui| {
                 // The start button
                 if ui.button("Start").clicked() {
-                    if *gamestate.current() != GameState::Gameplay {
-                        gamestate.set(GameState::Gameplay).unwrap();
+                    if *gamestate.current() != GameState::Lobby {
+                        gamestate.set(GameState::Lobby).unwrap();
                     }
-                    spawn_level_events.send(SpawnLevelEvent("".into()));
                 }
                 // The settings menu
                 if ui.button("Settings").clicked() {
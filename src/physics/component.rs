@@ -114,6 +114,15 @@ impl Default for Collider {
     }
 }
 
+impl Collider {
+    /// Check if the collider is blocked by another collider
+    pub fn collides_with(&self, other: &Collider) -> bool {
+        self.colliding_tags
+            .iter()
+            .all(|tag| other.tags.contains(tag))
+    }
+}
+
 /// A bundle for a physics bundle
 #[derive(Debug, Clone, Bundle)]
 pub struct PhysicsBodyBundle {
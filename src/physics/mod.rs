@@ -1,4 +1,4 @@
-use crate::prelude::GameState;
+use crate::prelude::{ActiveView, Dimensional, GameState, SimulationTime};
 
 use self::component::{
     Collider, CollidingDirections, CollidingEntities, Friction, GravityScale, PhysicsBody, Velocity,
@@ -42,22 +42,46 @@ impl Plugin for PhysicsPlugin {
                         .label("apply_velocity")
                         .after("handle_collision"),
                 )
+                .with_system(
+                    apply_gravity
+                        .label("finish_gravity")
+                        .after("apply_velocity"),
+                )
                 .with_system(
                     apply_friction
                         .label("apply_friction")
-                        .after("apply_velocity"),
+                        .after("finish_gravity"),
                 ),
         );
     }
 }
 
+/// Check if two axis aligned boxes overlap
+pub fn overlaps(position_a: Vec2, half_extents_a: Vec2, position_b: Vec2, half_extents_b: Vec2) -> bool {
+    (position_a.x - position_b.x).abs() < half_extents_a.x + half_extents_b.x
+        && (position_a.y - position_b.y).abs() < half_extents_a.y + half_extents_b.y
+}
+
 /// Handle collisions between the bodies
 fn handle_collisions(
-    time: Res<Time>,
+    time: Res<SimulationTime>,
+    physics_conf: Res<PhysicsConfig>,
+    active_view: Res<ActiveView>,
     mut collision_dirs: Query<&mut CollidingDirections>,
     mut colliding_entities: Query<&mut CollidingEntities>,
-    mut bodies: Query<(&mut Velocity, Entity, &Collider, &Transform, &PhysicsBody)>,
+    mut bodies: Query<(
+        &mut Velocity,
+        Entity,
+        &Collider,
+        &Transform,
+        &PhysicsBody,
+        Option<&Dimensional>,
+    )>,
 ) {
+    if !physics_conf.enabled {
+        return;
+    }
+
     // Reset the collision direction components
     collision_dirs.for_each_mut(|mut collision_dirs| {
         collision_dirs.clear();
@@ -70,9 +94,17 @@ fn handle_collisions(
     // Handle collisions
     let mut iter = bodies.iter_combinations_mut();
     while let Some(
-        [(mut velocity_a, entity_a, collider_a, transform_a, _), (mut velocity_b, entity_b, collider_b, transform_b, _)],
+        [(mut velocity_a, entity_a, collider_a, transform_a, _, dimensional_a), (mut velocity_b, entity_b, collider_b, transform_b, _, dimensional_b)],
     ) = iter.fetch_next()
     {
+        // Only the bodies in the active view can collide
+        let in_view = |dimensional: Option<&Dimensional>| {
+            dimensional.map_or(true, |dimensional| dimensional.exists_in(active_view.view))
+        };
+        if !in_view(dimensional_a) || !in_view(dimensional_b) {
+            continue;
+        }
+
         // Calulate the next positions for body a
         let next_x = transform_a.translation.x + velocity_a.x * time.delta_seconds();
         let next_y = transform_a.translation.y + velocity_a.y * time.delta_seconds();
@@ -94,11 +126,7 @@ fn handle_collisions(
             if velocity_a.x < 0.0 {
                 direction_a.x = -1.0;
             }
-            if collider_a
-                .colliding_tags
-                .iter()
-                .all(|tag| collider_b.tags.contains(tag))
-            {
+            if collider_a.collides_with(collider_b) {
                 velocity_a.x = 0.0;
             }
 
@@ -117,11 +145,7 @@ fn handle_collisions(
                 direction_a.y = -1.0;
             }
 
-            if collider_a
-                .colliding_tags
-                .iter()
-                .all(|tag| collider_b.tags.contains(tag))
-            {
+            if collider_a.collides_with(collider_b) {
                 velocity_a.y = 0.0;
             }
 
@@ -164,11 +188,7 @@ fn handle_collisions(
             if velocity_b.x < 0.0 {
                 direction_b.x = -1.0;
             }
-            if collider_b
-                .colliding_tags
-                .iter()
-                .all(|tag| collider_a.tags.contains(tag))
-            {
+            if collider_b.collides_with(collider_a) {
                 velocity_b.x = 0.0;
             }
 
@@ -185,11 +205,7 @@ fn handle_collisions(
             if velocity_b.y < 0.0 {
                 direction_b.y = -1.0;
             }
-            if collider_b
-                .colliding_tags
-                .iter()
-                .all(|tag| collider_a.tags.contains(tag))
-            {
+            if collider_b.collides_with(collider_a) {
                 velocity_b.y = 0.0;
             }
             colliding_entity_b = Some(entity_a);
@@ -213,21 +229,33 @@ fn handle_collisions(
     }
 }
 
-/// Apply the gravity to the body
+/// Apply half of the gravity to the body
+/// This runs before and after the velocity is applied, so the distance travelled under a
+/// constant gravity is exact and doesn't depend on the frame rate
 fn apply_gravity(
-    time: Res<Time>,
+    time: Res<SimulationTime>,
     physics_conf: Res<PhysicsConfig>,
     mut bodies: Query<(&mut Velocity, &PhysicsBody, &GravityScale)>,
 ) {
+    if !physics_conf.enabled {
+        return;
+    }
     for (mut velocity, body, scale) in bodies.iter_mut() {
         if *body == PhysicsBody::Dynamic {
-            velocity.0 += physics_conf.gravity * scale.0 * time.delta_seconds();
+            velocity.0 += physics_conf.gravity * scale.0 * time.delta_seconds() * 0.5;
         }
     }
 }
 
 /// Applies the velocity to the bodies
-fn apply_velocity(time: Res<Time>, mut bodies: Query<(&mut Transform, &PhysicsBody, &Velocity)>) {
+fn apply_velocity(
+    time: Res<SimulationTime>,
+    physics_conf: Res<PhysicsConfig>,
+    mut bodies: Query<(&mut Transform, &PhysicsBody, &Velocity)>,
+) {
+    if !physics_conf.enabled {
+        return;
+    }
     for (mut transform, body, velocity) in bodies.iter_mut() {
         if *body == PhysicsBody::Dynamic {
             transform.translation.x += velocity.x * time.delta_seconds();
@@ -237,7 +265,14 @@ fn apply_velocity(time: Res<Time>, mut bodies: Query<(&mut Transform, &PhysicsBo
 }
 
 /// Applies the friction to the bodies
-fn apply_friction(time: Res<Time>, mut bodies: Query<(&mut Velocity, &PhysicsBody, &Friction)>) {
+fn apply_friction(
+    time: Res<SimulationTime>,
+    physics_conf: Res<PhysicsConfig>,
+    mut bodies: Query<(&mut Velocity, &PhysicsBody, &Friction)>,
+) {
+    if !physics_conf.enabled {
+        return;
+    }
     for (mut velocity, body, friction) in bodies.iter_mut() {
         if *body == PhysicsBody::Dynamic {
             velocity.x += (friction.x * time.delta_seconds()) * (0f32 - velocity.x);
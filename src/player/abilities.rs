@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A movement ability that has to be unlocked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Ability {
+    DoubleJump,
+    Dash,
+    WallJump,
+    // The levels have no water or grapple points yet, so the movement doesn't check these two,
+    // they can already be unlocked by items and are kept in the saves
+    Swim,
+    Grapple,
+}
+
+/// The abilities a player has unlocked
+#[derive(Debug, Default, Clone, Component, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Abilities {
+    pub double_jump: bool,
+    pub dash: bool,
+    pub wall_jump: bool,
+    pub swim: bool,
+    pub grapple: bool,
+}
+
+impl Abilities {
+    /// Check if an ability is unlocked
+    pub fn has(&self, ability: Ability) -> bool {
+        match ability {
+            Ability::DoubleJump => self.double_jump,
+            Ability::Dash => self.dash,
+            Ability::WallJump => self.wall_jump,
+            Ability::Swim => self.swim,
+            Ability::Grapple => self.grapple,
+        }
+    }
+
+    /// Unlock an ability, returns false if it was already unlocked
+    pub fn grant(&mut self, ability: Ability) -> bool {
+        let flag = match ability {
+            Ability::DoubleJump => &mut self.double_jump,
+            Ability::Dash => &mut self.dash,
+            Ability::WallJump => &mut self.wall_jump,
+            Ability::Swim => &mut self.swim,
+            Ability::Grapple => &mut self.grapple,
+        };
+        let granted = !*flag;
+        *flag = true;
+        granted
+    }
+}
+
+/// When an item unlocks its ability
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GrantTrigger {
+    Collect,
+    Use,
+}
+
+/// The ability an item unlocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbilityGrant {
+    pub ability: Ability,
+    pub on: GrantTrigger,
+}
+
+/// An event that is sent when a player unlocks an ability
+#[derive(Debug, Clone)]
+pub struct AbilityUnlockedEvent {
+    pub entity: Entity,
+    pub ability: Ability,
+}
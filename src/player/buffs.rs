@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::simulation::SimulationTime;
+
+/// The stat a buff changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum BuffKind {
+    // Multiplies the horizontal acceleration
+    Speed,
+    // Multiplies the jump velocity
+    Jump,
+    // Makes the entity invulnerable, the amount is ignored
+    Invulnerability,
+}
+
+/// A temporary change to the stats of an entity
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buff {
+    pub kind: BuffKind,
+    // The multiplier the buff applies
+    pub amount: f32,
+    // The time left in seconds
+    pub remaining: f32,
+}
+
+/// The buffs that are active on an entity
+#[derive(Debug, Default, Clone, Component)]
+pub struct Buffs(pub Vec<Buff>);
+
+impl Buffs {
+    /// Add a buff, a buff of the same kind and amount is refreshed instead of stacked
+    pub fn add(&mut self, buff: Buff) {
+        match self
+            .0
+            .iter_mut()
+            .find(|active| active.kind == buff.kind && active.amount == buff.amount)
+        {
+            Some(active) => active.remaining = active.remaining.max(buff.remaining),
+            None => self.0.push(buff),
+        }
+    }
+
+    /// Get the combined multiplier of the buffs of a kind
+    pub fn multiplier(&self, kind: BuffKind) -> f32 {
+        self.0
+            .iter()
+            .filter(|buff| buff.kind == kind)
+            .map(|buff| buff.amount)
+            .product()
+    }
+
+    /// Check if a buff of a kind is active
+    pub fn has(&self, kind: BuffKind) -> bool {
+        self.0.iter().any(|buff| buff.kind == kind)
+    }
+}
+
+/// Counts down the buffs and removes the expired ones
+pub(crate) fn tick_buffs(time: Res<SimulationTime>, mut buffs: Query<&mut Buffs>) {
+    buffs.for_each_mut(|mut buffs| {
+        for buff in buffs.0.iter_mut() {
+            buff.remaining -= time.delta_seconds();
+        }
+        buffs.0.retain(|buff| buff.remaining > 0.0);
+    });
+}
@@ -1,9 +1,22 @@
 pub use bevy::prelude::*;
 
+use super::{abilities::Abilities, buffs::Buffs};
+use crate::{
+    combat::Facing,
+    items::{
+        equipment::{Equipment, StatModifiers},
+        shop::Wallet,
+    },
+};
+
 /// The market component for a player
 #[derive(Debug, Clone, Component, Default)]
 pub struct Player;
 
+/// The lobby slot a player belongs to
+#[derive(Debug, Default, Clone, Copy, Component, PartialEq, Eq)]
+pub struct PlayerSlot(pub usize);
+
 /// The player state
 #[derive(Debug, Default, Clone, Component)]
 pub struct PlayerState {
@@ -11,8 +24,68 @@ pub struct PlayerState {
     pub input: PlayerInputState,
     // The player's movement state
     pub movement: PlayerMovementState,
-    // The amount of time since the player jumped
-    pub time_since_jump: f32,
+    // The flag for if the player is rising from a jump they can still cut short
+    pub jump_active: bool,
+    // The flag for if the player's collider is shrunk to crouch
+    pub is_crouched: bool,
+    // The flag for if the player is sliding on the floor
+    pub is_sliding: bool,
+    // The ledge the player is hanging from
+    pub hang: Option<LedgeHang>,
+    // The mantle onto a ledge the player is doing
+    pub mantle: Option<Mantle>,
+    // The flag for if the jump button was held last frame
+    pub jump_held: bool,
+    // The flag for if the double jump was used since leaving the floor
+    pub double_jump_used: bool,
+    // The flag for if the dash was used since leaving the floor
+    pub dash_used: bool,
+    // The time left in the current dash
+    pub dash_time_left: f32,
+    // The time left before the player can dash again
+    pub dash_cooldown_left: f32,
+    // The direction of the current dash
+    pub dash_direction: f32,
+    // The flag for if the dash button was held last frame
+    pub dash_held: bool,
+    // The flag for if the flip button was held last frame
+    pub flip_held: bool,
+    // The flag for if the use item button was held last frame
+    pub use_held: bool,
+    // The flag for if the cycle item button was held last frame
+    pub cycle_held: bool,
+    // The flag for if the throw button was held last frame
+    pub throw_held: bool,
+    // The flag for if the interact button was pressed this frame
+    pub interact_pressed: bool,
+    // The flag for if the interact button was held last frame
+    pub interact_held: bool,
+    // The flag for if the attack button was held last frame
+    pub attack_held: bool,
+    // The flag for if the menu button was held last frame
+    pub menu_held: bool,
+}
+
+/// A ledge the player is hanging from
+#[derive(Debug, Clone, Copy)]
+pub struct LedgeHang {
+    // The top corner of the ledge
+    pub corner: Vec2,
+    // The direction of the wall, 1 to the right and -1 to the left
+    pub direction: f32,
+    // Whether the jump button was released since grabbing the ledge
+    pub jump_released: bool,
+}
+
+/// Pulls the player up onto a ledge
+#[derive(Debug, Clone, Copy)]
+pub struct Mantle {
+    // The position the mantle started at
+    pub from: Vec2,
+    // The position on top of the ledge
+    pub to: Vec2,
+    // The time spent mantling
+    pub elapsed: f32,
 }
 
 /// The enum that signifies what controller the player is using
@@ -37,12 +110,30 @@ pub struct PlayerInputState {
     pub is_jumping: bool,
     // The flag for if the player is sprinting
     pub is_sprinting: bool,
+    // The flag for if the player is crouching
+    pub is_crouching: bool,
+    // The flag for if the player is dashing
+    pub is_dashing: bool,
+    // The flag for if the player is flipping the view
+    pub is_flipping: bool,
+    // The flag for if the player is using the selected item
+    pub is_using_item: bool,
+    // The flag for if the player is selecting the next item
+    pub is_cycling_item: bool,
+    // The flag for if the player is throwing the selected item
+    pub is_throwing: bool,
+    // The flag for if the player is interacting with what they touch
+    pub is_interacting: bool,
+    // The flag for if the player is attacking with the equipped weapon
+    pub is_attacking: bool,
+    // The flag for if the player is opening the inventory menu
+    pub is_opening_menu: bool,
     // The horizontal movement
     pub horizontal: f32,
 }
 
 /// The player movement stats
-#[derive(Debug, Default, Component, Clone)]
+#[derive(Debug, Component, Clone)]
 pub struct PlayerMovementStats {
     // The player's walking accel
     pub walking_accel: f32,
@@ -50,19 +141,114 @@ pub struct PlayerMovementStats {
     pub sprint_accel: f32,
     // The walking friction
     pub walking_friction: f32,
-    // The player's jump force
-    pub jump_force: f32,
-    // The amount of time the player can jump
-    pub jump_time: f32,
+    // The height of a jump when the jump button is tapped
+    pub min_jump_height: f32,
+    // The height of a jump when the jump button is held
+    pub max_jump_height: f32,
+    // The time it takes to reach the apex of a full jump
+    pub time_to_apex: f32,
+    // The multiplier for the gravity while falling
+    pub fall_gravity_multiplier: f32,
+    // The half height of the collider while standing
+    pub stand_half_height: f32,
+    // The half height of the collider while crouching
+    pub crouch_half_height: f32,
+    // The multiplier for the walking accel while crouching
+    pub crouch_speed_multiplier: f32,
+    // The horizontal speed needed to turn a crouch into a slide
+    pub slide_min_speed: f32,
+    // The friction while sliding
+    pub slide_friction: f32,
+    // The flag for if the player can grab ledges
+    pub ledge_grab_enabled: bool,
+    // The distance between the hands and a ledge that still counts as reaching it
+    pub ledge_grab_range: f32,
+    // The time it takes to pull up onto a ledge
+    pub mantle_time: f32,
+    // The horizontal speed of a dash
+    pub dash_speed: f32,
+    // The time a dash lasts
+    pub dash_time: f32,
+    // The time between two dashes
+    pub dash_cooldown: f32,
+    // The horizontal speed a wall jump pushes the player away from the wall
+    pub wall_jump_push: f32,
+}
+
+impl Default for PlayerMovementStats {
+    fn default() -> Self {
+        Self {
+            walking_accel: 325.0,
+            sprint_accel: 450.0,
+            walking_friction: 6.4,
+            min_jump_height: 12.0,
+            max_jump_height: 48.0,
+            time_to_apex: 0.4,
+            fall_gravity_multiplier: 1.6,
+            stand_half_height: 10.0,
+            crouch_half_height: 5.0,
+            crouch_speed_multiplier: 0.4,
+            slide_min_speed: 40.0,
+            slide_friction: 1.2,
+            ledge_grab_enabled: true,
+            ledge_grab_range: 4.0,
+            mantle_time: 0.25,
+            dash_speed: 220.0,
+            dash_time: 0.15,
+            dash_cooldown: 0.4,
+            wall_jump_push: 120.0,
+        }
+    }
+}
+
+impl PlayerMovementStats {
+    /// The gravity needed to reach the max jump height in the time to apex
+    pub fn jump_gravity(&self) -> f32 {
+        if self.time_to_apex <= 0.0 {
+            return 0.0;
+        }
+        2.0 * self.max_jump_height / (self.time_to_apex * self.time_to_apex)
+    }
+
+    /// The initial velocity of a jump
+    pub fn jump_velocity(&self) -> f32 {
+        if self.time_to_apex <= 0.0 {
+            return 0.0;
+        }
+        2.0 * self.max_jump_height / self.time_to_apex
+    }
+
+    /// The velocity a jump is cut to when the jump button is released early
+    pub fn min_jump_velocity(&self) -> f32 {
+        (2.0 * self.jump_gravity() * self.min_jump_height).sqrt()
+    }
+
+    /// The gravity scale that turns the world gravity into the jump gravity
+    pub fn gravity_scale(&self, world_gravity: f32, falling: bool) -> f32 {
+        if world_gravity == 0.0 {
+            return 0.0;
+        }
+        let scale = self.jump_gravity() / world_gravity.abs();
+        if falling {
+            scale * self.fall_gravity_multiplier
+        } else {
+            scale
+        }
+    }
 }
 
 /// The player movement state
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PlayerMovementState {
     Idle,
     Walking,
     Jumping,
     Falling,
+    Crouching,
+    Sliding,
+    Hanging,
+    Mantling,
+    Dashing,
 }
 
 impl Default for PlayerMovementState {
@@ -71,10 +257,109 @@ impl Default for PlayerMovementState {
     }
 }
 
+/// A rule for how the animation changes when the movement state changes
+#[derive(Debug, Clone)]
+pub struct AnimationTransition {
+    // The state the player leaves, or any state if none
+    pub from: Option<PlayerMovementState>,
+    // The state the player enters
+    pub to: PlayerMovementState,
+    // A one-shot clip that is played before the clip of the new state
+    pub via: Option<String>,
+    // Let the current one-shot clip finish before switching
+    pub wait_for_finish: bool,
+}
+
+/// Maps the player movement states to animation clips
+#[derive(Debug, Clone, Component)]
+pub struct PlayerAnimations {
+    // The clip played for each movement state
+    pub clips: Vec<(PlayerMovementState, String)>,
+    // The rules for switching between the clips
+    pub transitions: Vec<AnimationTransition>,
+    // The movement state of the previous frame
+    pub last_state: Option<PlayerMovementState>,
+}
+
+impl Default for PlayerAnimations {
+    fn default() -> Self {
+        Self {
+            clips: vec![
+                (PlayerMovementState::Idle, "idle".into()),
+                (PlayerMovementState::Walking, "run".into()),
+                (PlayerMovementState::Jumping, "jump".into()),
+                (PlayerMovementState::Falling, "fall".into()),
+                (PlayerMovementState::Crouching, "crouch".into()),
+                (PlayerMovementState::Sliding, "slide".into()),
+                (PlayerMovementState::Hanging, "hang".into()),
+                (PlayerMovementState::Mantling, "mantle".into()),
+                (PlayerMovementState::Dashing, "dash".into()),
+            ],
+            transitions: vec![
+                // Land before standing still or running
+                AnimationTransition {
+                    from: Some(PlayerMovementState::Falling),
+                    to: PlayerMovementState::Idle,
+                    via: Some("land".into()),
+                    wait_for_finish: false,
+                },
+                AnimationTransition {
+                    from: Some(PlayerMovementState::Falling),
+                    to: PlayerMovementState::Walking,
+                    via: Some("land".into()),
+                    wait_for_finish: false,
+                },
+                // Don't cut the landing short when standing still
+                AnimationTransition {
+                    from: None,
+                    to: PlayerMovementState::Idle,
+                    via: None,
+                    wait_for_finish: true,
+                },
+            ],
+            last_state: None,
+        }
+    }
+}
+
+impl PlayerAnimations {
+    /// Get the clip for a movement state
+    pub fn clip(&self, state: PlayerMovementState) -> Option<&str> {
+        self.clips
+            .iter()
+            .find(|(clip_state, _)| *clip_state == state)
+            .map(|(_, clip)| clip.as_str())
+    }
+
+    /// Get the transition rule between two states, preferring an exact match over a wildcard
+    pub fn transition(
+        &self,
+        from: Option<PlayerMovementState>,
+        to: PlayerMovementState,
+    ) -> Option<&AnimationTransition> {
+        self.transitions
+            .iter()
+            .find(|rule| rule.to == to && rule.from.is_some() && rule.from == from)
+            .or_else(|| {
+                self.transitions
+                    .iter()
+                    .find(|rule| rule.to == to && rule.from.is_none())
+            })
+    }
+}
+
 /// The bundle for player components
 #[derive(Debug, Default, Bundle, Clone)]
 pub struct PlayerBundle {
     pub player: Player,
+    pub player_slot: PlayerSlot,
     pub player_state: PlayerState,
     pub player_movement_stats: PlayerMovementStats,
+    pub player_animations: PlayerAnimations,
+    pub abilities: Abilities,
+    pub buffs: Buffs,
+    pub equipment: Equipment,
+    pub stat_modifiers: StatModifiers,
+    pub wallet: Wallet,
+    pub facing: Facing,
 }
@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+use super::component::{Player, PlayerMovementStats, PlayerState};
+use crate::prelude::{overlaps, ActiveView, Collider, CollidingDirections, Dimensional, Velocity};
+
+/// Shrinks and grows the player's collider when crouching, keeping the feet in place
+pub(crate) fn handle_crouch(
+    active_view: Res<ActiveView>,
+    colliders: Query<(&Collider, &Transform, Option<&Dimensional>), Without<Player>>,
+    mut players: Query<
+        (
+            &mut PlayerState,
+            &PlayerMovementStats,
+            &mut Collider,
+            &mut Transform,
+            &CollidingDirections,
+            &Velocity,
+        ),
+        With<Player>,
+    >,
+) {
+    players.for_each_mut(
+        |(mut state, stats, mut collider, mut transform, collision_dir, velocity)| {
+            let on_floor = collision_dir.contains(&Vec2::new(0.0, -1.0));
+            let height_difference = stats.stand_half_height - stats.crouch_half_height;
+
+            // Crouch down, sprinting into a crouch turns into a slide
+            if state.input.is_crouching && on_floor && !state.is_crouched {
+                collider.half_extents.y = stats.crouch_half_height;
+                transform.translation.y -= height_difference;
+                state.is_crouched = true;
+                state.is_sliding =
+                    state.input.is_sprinting && velocity.x.abs() >= stats.slide_min_speed;
+            }
+
+            if !state.is_crouched {
+                return;
+            }
+
+            // The slide ends once the player slowed down
+            if state.is_sliding && velocity.x.abs() < stats.slide_min_speed * 0.5 {
+                state.is_sliding = false;
+            }
+
+            // Stand back up, unless there's a ceiling in the way
+            if !state.input.is_crouching {
+                let standing_position = Vec2::new(
+                    transform.translation.x,
+                    transform.translation.y + height_difference,
+                );
+                let standing_half_extents =
+                    Vec2::new(collider.half_extents.x, stats.stand_half_height);
+
+                // Only the colliders in the active view are in the way
+                let blocked = colliders.iter().any(|(other, other_transform, dimensional)| {
+                    dimensional.map_or(true, |dimensional| dimensional.exists_in(active_view.view))
+                        && collider.collides_with(other)
+                        && overlaps(
+                            standing_position,
+                            standing_half_extents,
+                            other_transform.translation.truncate(),
+                            other.half_extents,
+                        )
+                });
+
+                if !blocked {
+                    collider.half_extents.y = stats.stand_half_height;
+                    transform.translation.y += height_difference;
+                    state.is_crouched = false;
+                    state.is_sliding = false;
+                }
+            }
+        },
+    );
+}
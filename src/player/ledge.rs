@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+
+use super::component::{LedgeHang, Mantle, Player, PlayerMovementStats, PlayerState};
+use crate::prelude::{
+    overlaps, ActiveView, Collider, CollidingDirections, Dimensional, PhysicsBody, SimulationTime,
+    Velocity,
+};
+
+/// The horizontal gap between the player and a wall that still counts as touching it
+const WALL_CONTACT_DISTANCE: f32 = 1.5;
+
+/// Grabs the top corners of walls, and handles hanging from and pulling up onto them
+pub(crate) fn handle_ledges(
+    time: Res<SimulationTime>,
+    active_view: Res<ActiveView>,
+    colliders: Query<(&Collider, &Transform, &PhysicsBody, Option<&Dimensional>), Without<Player>>,
+    mut players: Query<
+        (
+            &mut PlayerState,
+            &PlayerMovementStats,
+            &Collider,
+            &mut Transform,
+            &mut Velocity,
+            &CollidingDirections,
+        ),
+        With<Player>,
+    >,
+) {
+    players.for_each_mut(
+        |(mut state, stats, collider, mut transform, mut velocity, collision_dir)| {
+            let position = transform.translation.truncate();
+            let half_extents = collider.half_extents;
+
+            // Pull up onto the ledge
+            if let Some(mut mantle) = state.mantle {
+                mantle.elapsed += time.delta_seconds();
+                let progress = if stats.mantle_time > 0.0 {
+                    (mantle.elapsed / stats.mantle_time).min(1.0)
+                } else {
+                    1.0
+                };
+                // Rise first, then move over the ledge
+                let vertical = (progress * 2.0).min(1.0);
+                let horizontal = (progress * 2.0 - 1.0).max(0.0);
+                transform.translation.x = mantle.from.x + (mantle.to.x - mantle.from.x) * horizontal;
+                transform.translation.y = mantle.from.y + (mantle.to.y - mantle.from.y) * vertical;
+                velocity.0 = Vec2::ZERO;
+
+                state.mantle = if progress >= 1.0 { None } else { Some(mantle) };
+                return;
+            }
+
+            // Hang from the ledge until the player pulls up or lets go
+            if let Some(mut hang) = state.hang {
+                velocity.0 = Vec2::ZERO;
+
+                if state.input.is_crouching || !stats.ledge_grab_enabled {
+                    state.hang = None;
+                } else if state.input.is_jumping && hang.jump_released {
+                    state.hang = None;
+                    state.mantle = Some(Mantle {
+                        from: position,
+                        to: Vec2::new(
+                            hang.corner.x + hang.direction * half_extents.x,
+                            hang.corner.y + half_extents.y,
+                        ),
+                        elapsed: 0.0,
+                    });
+                } else {
+                    hang.jump_released |= !state.input.is_jumping;
+                    state.hang = Some(hang);
+                }
+                return;
+            }
+
+            // Only grab ledges while in the air and reaching towards a wall
+            let on_floor = collision_dir.contains(&Vec2::new(0.0, -1.0));
+            if !stats.ledge_grab_enabled
+                || on_floor
+                || state.is_crouched
+                || state.input.horizontal.abs() < 0.5
+            {
+                return;
+            }
+            let direction = state.input.horizontal.signum();
+            // Only the colliders in the active view can be grabbed or get in the way
+            let in_view = |dimensional: Option<&Dimensional>| {
+                dimensional.map_or(true, |dimensional| dimensional.exists_in(active_view.view))
+            };
+            let hands = Vec2::new(position.x + direction * half_extents.x, position.y + half_extents.y);
+
+            for (other, other_transform, body, dimensional) in colliders.iter() {
+                if *body != PhysicsBody::Static
+                    || !collider.collides_with(other)
+                    || !in_view(dimensional)
+                {
+                    continue;
+                }
+                let other_position = other_transform.translation.truncate();
+
+                // The top corner of the wall facing the player
+                let corner = Vec2::new(
+                    other_position.x - direction * other.half_extents.x,
+                    other_position.y + other.half_extents.y,
+                );
+                if (hands.x - corner.x).abs() > WALL_CONTACT_DISTANCE
+                    || (hands.y - corner.y).abs() > stats.ledge_grab_range
+                {
+                    continue;
+                }
+
+                // There has to be room to stand on top of the ledge
+                let standing_position = Vec2::new(
+                    corner.x + direction * half_extents.x,
+                    corner.y + half_extents.y,
+                );
+                let blocked = colliders.iter().any(|(blocker, blocker_transform, _, dimensional)| {
+                    in_view(dimensional)
+                        && collider.collides_with(blocker)
+                        && overlaps(
+                            standing_position,
+                            half_extents,
+                            blocker_transform.translation.truncate(),
+                            blocker.half_extents,
+                        )
+                });
+                if blocked {
+                    continue;
+                }
+
+                // Snap the hands to the corner
+                transform.translation.x = corner.x - direction * half_extents.x;
+                transform.translation.y = corner.y - half_extents.y;
+                velocity.0 = Vec2::ZERO;
+                state.jump_active = false;
+                state.hang = Some(LedgeHang {
+                    corner,
+                    direction,
+                    jump_released: !state.input.is_jumping,
+                });
+                break;
+            }
+        },
+    );
+}
@@ -1,11 +1,30 @@
 use bevy::prelude::*;
 
-use crate::prelude::{CollidingDirections, Velocity, GameState};
+use crate::{
+    prelude::{
+        AnimationClip, Animator, CollidingDirections, Facing, GameState, GravityScale,
+        PhysicsConfig, PlaybackMode, SimulationTime, StatModifiers, Velocity,
+    },
+    replay::ReplayMode,
+};
 
-use self::component::{
-    ControllerState, Player, PlayerMovementState, PlayerMovementStats, PlayerState,
+use self::{
+    abilities::{Abilities, Ability, AbilityUnlockedEvent},
+    buffs::{tick_buffs, BuffKind, Buffs},
+    component::{
+        ControllerState, Player, PlayerAnimations, PlayerMovementState, PlayerMovementStats,
+        PlayerSlot, PlayerState,
+    },
+    crouch::handle_crouch,
+    ledge::handle_ledges,
+    slots::PlayerSlots,
 };
+pub mod abilities;
+pub mod buffs;
 pub mod component;
+pub mod crouch;
+pub mod ledge;
+pub mod slots;
 
 /// The plugin that handles the player controls
 pub struct PlayerPlugin;
@@ -13,9 +32,23 @@ pub struct PlayerPlugin;
 // Implement the plugin for player
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerSlots>();
+        app.add_event::<AbilityUnlockedEvent>();
+        app.add_system(handle_gamepad_connections);
         app.add_system_set(SystemSet::on_update(GameState::Gameplay)
-            .with_system(check_controller.label("check_controller"))
-            .with_system(update_input.label("update_input").after("check_controller"))
+            .with_system(update_input.label("update_input"))
+            .with_system(
+                handle_crouch
+                    .label("handle_crouch")
+                    .after("update_input")
+                    .before("update_movement_state"),
+            )
+            .with_system(
+                handle_ledges
+                    .label("handle_ledges")
+                    .after("handle_crouch")
+                    .before("update_movement_state"),
+            )
             .with_system(
                 update_movement_state
                     .label("update_movement_state")
@@ -24,69 +57,144 @@ impl Plugin for PlayerPlugin {
             .with_system(
                 handle_movement
                     .label("handle_movement")
-                    .after("update_movement_state"),
+                    .after("update_movement_state")
+                    .before("apply_gravity"),
+            )
+            .with_system(tick_buffs.after("handle_movement"))
+            .with_system(animate.after("update_facing"))
+            .with_system(
+                update_animation
+                    .after("update_movement_state")
+                    .before("advance_animations"),
             )
-            .with_system(animate)
         );
     }
 }
 
-/// Checks what controller the player is using and updates the player state
-fn check_controller(
-    keyboard_input: Res<Input<KeyCode>>,
-    gamepad_input: Res<Input<GamepadButton>>,
-    mut states: Query<&mut PlayerState>,
+/// Keeps track of the connected gamepads
+fn handle_gamepad_connections(
+    mut slots: ResMut<PlayerSlots>,
+    mut gamepad_events: EventReader<GamepadEvent>,
 ) {
-    states.for_each_mut(|mut state| {
-        // Check if the player is using a keyboard
-        if keyboard_input.get_pressed().len() > 0 {
-            state.input.controller = ControllerState::Keyboard;
-        }
-
-        // Check if the player is using a gamepad
-        if gamepad_input.get_pressed().len() > 0 {
-            state.input.controller = ControllerState::Gamepad(0);
+    for GamepadEvent(gamepad, event) in gamepad_events.iter() {
+        match event {
+            GamepadEventType::Connected => {
+                info!("Gamepad {} connected", gamepad.0);
+                if !slots.connected_gamepads.contains(&gamepad.0) {
+                    slots.connected_gamepads.push(gamepad.0);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                info!("Gamepad {} disconnected", gamepad.0);
+                slots.connected_gamepads.retain(|id| *id != gamepad.0);
+            }
+            _ => {}
         }
-    });
+    }
 }
 
-/// Update the input for all the players
+/// Update the input for all the players from the device bound to them, or from the replay that
+/// is played back
+/// Every system that reads the input has to run after this one
 fn update_input(
+    slots: Res<PlayerSlots>,
+    replay_mode: Res<ReplayMode>,
+    simulation_time: Res<SimulationTime>,
     keyboard_input: Res<Input<KeyCode>>,
     gamepad_axis_input: Res<Axis<GamepadAxis>>,
     gamepad_button_input: Res<Input<GamepadButton>>,
-    mut states: Query<&mut PlayerState>,
+    mut states: Query<(&PlayerSlot, &mut PlayerState)>,
 ) {
-    states.for_each_mut(|mut state| {
+    states.for_each_mut(|(slot, mut state)| {
+        if let Some(input) = replay_mode.playback_input(simulation_time.tick, slot.0) {
+            let controller = state.input.controller;
+            state.input = input;
+            state.input.controller = controller;
+            return;
+        }
+
+        // A disconnected device doesn't send any input
+        if !slots.is_connected(state.input.controller) {
+            state.input.is_jumping = false;
+            state.input.is_sprinting = false;
+            state.input.is_crouching = false;
+            state.input.is_dashing = false;
+            state.input.is_flipping = false;
+            state.input.is_using_item = false;
+            state.input.is_cycling_item = false;
+            state.input.is_throwing = false;
+            state.input.is_interacting = false;
+            state.input.is_attacking = false;
+            state.input.is_opening_menu = false;
+            state.input.horizontal = 0.0;
+            return;
+        }
+
         match state.input.controller {
             ControllerState::Keyboard => {
                 // Check if the player is jumping or sprinting
                 state.input.is_jumping = keyboard_input.pressed(KeyCode::Space);
                 state.input.is_sprinting = keyboard_input.pressed(KeyCode::LShift);
+                state.input.is_crouching = keyboard_input.pressed(KeyCode::S);
+                state.input.is_dashing = keyboard_input.pressed(KeyCode::K);
+                state.input.is_flipping = keyboard_input.pressed(KeyCode::F);
+                state.input.is_using_item = keyboard_input.pressed(KeyCode::E);
+                state.input.is_cycling_item = keyboard_input.pressed(KeyCode::Tab);
+                state.input.is_throwing = keyboard_input.pressed(KeyCode::T);
+                state.input.is_interacting = keyboard_input.pressed(KeyCode::W);
+                state.input.is_attacking = keyboard_input.pressed(KeyCode::J);
+                state.input.is_opening_menu = keyboard_input.pressed(KeyCode::Q);
 
                 // Get the horizontal input
                 state.input.horizontal = -(keyboard_input.pressed(KeyCode::A) as i8 as f32)
                     + keyboard_input.pressed(KeyCode::D) as i8 as f32;
             }
-            ControllerState::Gamepad(_) => {
+            ControllerState::Gamepad(id) => {
+                let gamepad = Gamepad(id);
+
                 // Check if the player is jumping or sprinting
                 state.input.is_jumping = gamepad_button_input
-                    .pressed(GamepadButton(Gamepad(0), GamepadButtonType::South));
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::South));
                 state.input.is_sprinting = gamepad_button_input
-                    .pressed(GamepadButton(Gamepad(0), GamepadButtonType::West));
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::West));
+                state.input.is_crouching = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::DPadDown))
+                    || gamepad_axis_input
+                        .get(GamepadAxis(gamepad, GamepadAxisType::LeftStickY))
+                        .unwrap_or(0.0)
+                        < -0.5;
+                state.input.is_dashing = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::RightTrigger));
+                state.input.is_flipping = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::North));
+                state.input.is_using_item = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::East));
+                state.input.is_cycling_item = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::LeftTrigger));
+                state.input.is_throwing = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::RightTrigger2));
+                state.input.is_interacting = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::DPadUp));
+                state.input.is_attacking = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::LeftTrigger2));
+                state.input.is_opening_menu = gamepad_button_input
+                    .pressed(GamepadButton(gamepad, GamepadButtonType::Select));
 
                 // Get the horizontal input
                 state.input.horizontal = gamepad_axis_input
-                    .get(GamepadAxis(Gamepad(0), GamepadAxisType::LeftStickX))
-                    .unwrap();
+                    .get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX))
+                    .unwrap_or(0.0);
             }
         }
     });
 }
 
 /// Update the player movement state
-fn update_movement_state(mut states: Query<(&mut PlayerState, &Velocity)>) {
-    states.for_each_mut(|(mut state, velocity)| {
+fn update_movement_state(mut states: Query<(&mut PlayerState, &Velocity, &CollidingDirections)>) {
+    states.for_each_mut(|(mut state, velocity, collision_dir)| {
+        // Check if the player is on the floor
+        let on_floor = collision_dir.contains(&Vec2::new(0.0, -1.0));
+
         // Default to idle
         state.movement = PlayerMovementState::Idle;
         // Check if walking
@@ -98,72 +206,223 @@ fn update_movement_state(mut states: Query<(&mut PlayerState, &Velocity)>) {
             state.movement = PlayerMovementState::Jumping;
         }
         // Check if falling
-        if velocity.y < 0.0 {
+        if velocity.y < 0.0 && !on_floor {
             state.movement = PlayerMovementState::Falling;
         }
+        // Check if crouching or sliding
+        if state.is_crouched && on_floor {
+            state.movement = if state.is_sliding {
+                PlayerMovementState::Sliding
+            } else {
+                PlayerMovementState::Crouching
+            };
+        }
+        // Check if hanging from or pulling up onto a ledge
+        if state.hang.is_some() {
+            state.movement = PlayerMovementState::Hanging;
+        }
+        if state.mantle.is_some() {
+            state.movement = PlayerMovementState::Mantling;
+        }
+        // Check if dashing
+        if state.dash_time_left > 0.0 {
+            state.movement = PlayerMovementState::Dashing;
+        }
     });
 }
 
 /// Handle player movement
 fn handle_movement(
-    time: Res<Time>,
+    time: Res<SimulationTime>,
+    physics_conf: Res<PhysicsConfig>,
     mut states: Query<(
         &mut PlayerState,
         &CollidingDirections,
         &PlayerMovementStats,
         &mut Velocity,
+        &mut GravityScale,
+        &Abilities,
+        &Buffs,
+        &StatModifiers,
     )>,
 ) {
-    states.for_each_mut(|(mut state, collision_dir, stats, mut velocity)| {
+    states.for_each_mut(|(mut state, collision_dir, stats, mut velocity, mut gravity_scale, abilities, buffs, modifiers)| {
+        // Check which buttons were pressed this frame
+        let jump_pressed = state.input.is_jumping && !state.jump_held;
+        let dash_pressed = state.input.is_dashing && !state.dash_held;
+        state.jump_held = state.input.is_jumping;
+        state.dash_held = state.input.is_dashing;
+
+        // The ledge handles the movement while hanging or mantling
+        if state.hang.is_some() || state.mantle.is_some() {
+            gravity_scale.0 = 0.0;
+            return;
+        }
+
         // Check if the player is on the floot
         let on_floor = collision_dir.contains(&Vec2::new(0.0, -1.0));
+        // Check if the player is pushing against a wall while in the air
+        let wall = collision_dir
+            .iter()
+            .find(|dir| dir.x != 0.0 && dir.y == 0.0)
+            .map_or(0.0, |dir| dir.x);
 
-        // Check if the player is sprinting
-        let speed = if state.input.is_sprinting {
+        // The air moves are restored on the floor
+        if on_floor {
+            state.double_jump_used = false;
+            state.dash_used = false;
+        }
+
+        // Handle the dashing
+        state.dash_cooldown_left = (state.dash_cooldown_left - time.delta_seconds()).max(0.0);
+        if dash_pressed
+            && abilities.has(Ability::Dash)
+            && !state.dash_used
+            && state.dash_cooldown_left <= 0.0
+            && !state.is_crouched
+        {
+            state.dash_direction = if state.input.horizontal != 0.0 {
+                state.input.horizontal.signum()
+            } else if velocity.x < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            state.dash_time_left = stats.dash_time;
+            state.dash_cooldown_left = stats.dash_time + stats.dash_cooldown;
+            // Dashing on the floor doesn't use up the air dash
+            state.dash_used = !on_floor;
+            state.jump_active = false;
+        }
+        if state.dash_time_left > 0.0 {
+            state.dash_time_left -= time.delta_seconds();
+            velocity.x = state.dash_direction * stats.dash_speed;
+            velocity.y = 0.0;
+            gravity_scale.0 = 0.0;
+            return;
+        }
+
+        // Check if the player is sprinting or crouching
+        let speed = if state.is_sliding {
+            0.0
+        } else if state.is_crouched {
+            stats.walking_accel * stats.crouch_speed_multiplier
+        } else if state.input.is_sprinting {
             stats.sprint_accel
         } else {
             stats.walking_accel
-        };
+        } * buffs.multiplier(BuffKind::Speed)
+            * modifiers.speed;
+        let jump_velocity =
+            stats.jump_velocity() * buffs.multiplier(BuffKind::Jump) * modifiers.jump;
 
         // Handle the jumping
-        if on_floor {
-            state.time_since_jump = 0.0;
-        }
-        if state.input.is_jumping {
-            if on_floor {
-                // The initial jump force
-                velocity.y = stats.jump_force * time.delta_seconds();
-            } else if state.time_since_jump < stats.jump_time {
-                velocity.y += stats.jump_force * time.delta_seconds();
-                state.time_since_jump += time.delta_seconds();
+        if state.input.is_jumping && on_floor && !state.is_crouched {
+            // The jump velocity is derived from the jump height, so it doesn't depend on the frame rate
+            velocity.y = jump_velocity;
+            state.jump_active = true;
+        } else if jump_pressed && !on_floor {
+            if wall != 0.0 && abilities.has(Ability::WallJump) {
+                // Kick off the wall
+                velocity.x = -wall * stats.wall_jump_push;
+                velocity.y = jump_velocity;
+                state.jump_active = true;
+            } else if abilities.has(Ability::DoubleJump) && !state.double_jump_used {
+                velocity.y = jump_velocity;
+                state.jump_active = true;
+                state.double_jump_used = true;
             }
-        } else {
-            state.time_since_jump = stats.jump_time;
         }
+        if state.jump_active {
+            if velocity.y <= 0.0 {
+                state.jump_active = false;
+            } else if !state.input.is_jumping {
+                // Cut the jump short when the button is released
+                velocity.y = velocity.y.min(stats.min_jump_velocity());
+                state.jump_active = false;
+            }
+        }
+
+        // Derive the gravity from the jump, falling faster than rising
+        gravity_scale.0 = stats.gravity_scale(physics_conf.gravity.y, velocity.y < 0.0);
+
         // Check if the player is walking
         if state.movement == PlayerMovementState::Walking
             || state.movement == PlayerMovementState::Jumping
             || state.movement == PlayerMovementState::Falling
+            || state.movement == PlayerMovementState::Crouching
         {
             // Move the player
             velocity.x += state.input.horizontal * speed * time.delta_seconds();
         }
-        // Apply the friction
-        velocity.x = velocity.x * (1.0 - stats.walking_friction * time.delta_seconds());
+        // Apply the friction, sliding keeps most of the momentum
+        let friction = if state.is_sliding {
+            stats.slide_friction
+        } else {
+            stats.walking_friction
+        };
+        velocity.x = velocity.x * (1.0 - friction * time.delta_seconds());
     });
 }
 
 /// Animates the player
-fn animate(mut players: Query<(&mut Sprite, &Velocity), With<Player>>) {
-    players.for_each_mut(|(mut sprite, velocity)| {
-        // Check if the player is walking
-        if velocity.x.abs() > 0.0 {
-            // Check if the player is facing left
-            if velocity.x < 0.0 {
-                sprite.flip_x = true;
-            } else {
-                sprite.flip_x = false;
+fn animate(mut players: Query<(&mut TextureAtlasSprite, &Facing), With<Player>>) {
+    players.for_each_mut(|(mut sprite, facing)| {
+        // The sprite sheet faces right
+        sprite.flip_x = *facing == Facing::Left;
+    });
+}
+
+/// Switches the animation clip when the movement state changes
+fn update_animation(
+    mut players: Query<(&PlayerState, &mut PlayerAnimations, &mut Animator), With<Player>>,
+) {
+    players.for_each_mut(|(state, mut animations, mut animator)| {
+        if animations.last_state == Some(state.movement) {
+            return;
+        }
+        let last_state = animations.last_state.replace(state.movement);
+
+        let clip = match animations.clip(state.movement) {
+            Some(clip) => clip.to_string(),
+            None => return,
+        };
+        match animations.transition(last_state, state.movement) {
+            Some(rule) => {
+                if let Some(via) = &rule.via {
+                    animator.restart(via);
+                    animator.queue(&clip);
+                } else if rule.wait_for_finish {
+                    animator.queue(&clip);
+                } else {
+                    animator.play(&clip);
+                }
             }
+            None => animator.play(&clip),
         }
     });
 }
+
+/// Creates the animator with the player's clips
+/// The sprite sheet has a row of 8 frames per clip
+pub fn player_animator() -> Animator {
+    Animator::default()
+        .with_clip("idle", AnimationClip::from_range(0, 3, 0.2, PlaybackMode::Loop))
+        .with_clip(
+            "run",
+            AnimationClip::from_range(8, 13, 0.08, PlaybackMode::Loop)
+                .with_event(1, "footstep")
+                .with_event(4, "footstep"),
+        )
+        .with_clip("jump", AnimationClip::from_range(16, 17, 0.1, PlaybackMode::Once))
+        .with_clip("fall", AnimationClip::from_range(24, 25, 0.1, PlaybackMode::Loop))
+        .with_clip(
+            "land",
+            AnimationClip::from_range(32, 34, 0.05, PlaybackMode::Once).with_event(0, "land"),
+        )
+        .with_clip("crouch", AnimationClip::from_range(40, 41, 0.3, PlaybackMode::Loop))
+        .with_clip("slide", AnimationClip::from_range(48, 49, 0.1, PlaybackMode::Loop))
+        .with_clip("hang", AnimationClip::from_range(56, 57, 0.4, PlaybackMode::Loop))
+        .with_clip("mantle", AnimationClip::from_range(64, 67, 0.06, PlaybackMode::Once))
+        .with_clip("dash", AnimationClip::from_range(72, 73, 0.05, PlaybackMode::Loop))
+}
@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+
+use super::component::ControllerState;
+
+/// The maximum amount of players that can join a game
+pub const MAX_PLAYERS: usize = 4;
+
+/// Keeps track of which device claimed which player slot
+#[derive(Debug, Clone)]
+pub struct PlayerSlots {
+    /// The controller bound to each slot, if any
+    pub slots: [Option<ControllerState>; MAX_PLAYERS],
+    /// The gamepads that are currently connected
+    pub connected_gamepads: Vec<usize>,
+}
+
+impl Default for PlayerSlots {
+    fn default() -> Self {
+        Self {
+            slots: [None; MAX_PLAYERS],
+            connected_gamepads: vec![],
+        }
+    }
+}
+
+impl PlayerSlots {
+    /// Claim the first free slot for a controller, returns the claimed slot
+    pub fn join(&mut self, controller: ControllerState) -> Option<usize> {
+        // A controller can only claim a single slot
+        if let Some(slot) = self.slot_of(controller) {
+            return Some(slot);
+        }
+        let slot = self.slots.iter().position(|slot| slot.is_none())?;
+        self.slots[slot] = Some(controller);
+        Some(slot)
+    }
+
+    /// Release the slot claimed by a controller
+    pub fn leave(&mut self, controller: ControllerState) -> Option<usize> {
+        let slot = self.slot_of(controller)?;
+        self.slots[slot] = None;
+        Some(slot)
+    }
+
+    /// Release all the slots
+    pub fn clear(&mut self) {
+        self.slots = [None; MAX_PLAYERS];
+    }
+
+    /// Get the slot claimed by a controller
+    pub fn slot_of(&self, controller: ControllerState) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| *slot == Some(controller))
+    }
+
+    /// Iterate over the claimed slots and their controllers
+    pub fn joined(&self) -> impl Iterator<Item = (usize, ControllerState)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, controller)| controller.map(|controller| (slot, controller)))
+    }
+
+    /// Check if a controller is currently able to send input
+    pub fn is_connected(&self, controller: ControllerState) -> bool {
+        match controller {
+            ControllerState::Keyboard => true,
+            ControllerState::Gamepad(id) => self.connected_gamepads.contains(&id),
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    dimension::{ActiveView, Dimensional},
+    health::{DamageEvent, Health},
+    levels::{DestroyLevelsEvent, LevelObject},
+    physics::component::{
+        Collider, CollidingEntities, GravityScale, PhysicsBody, PhysicsBodyBundle, Velocity,
+    },
+    simulation::SimulationTime,
+    state::GameState,
+};
+
+/// What a projectile does when it touches the ground
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum GroundContact {
+    // The projectile is gone
+    Despawn,
+    // The projectile bounces off, keeping a part of its speed
+    Bounce { restitution: f32 },
+}
+
+impl Default for GroundContact {
+    fn default() -> Self {
+        Self::Despawn
+    }
+}
+
+/// A projectile that damages the colliders it hits
+#[derive(Debug, Clone, Component)]
+pub struct Projectile {
+    // The entity that fired the projectile, it can't be hit by it
+    pub owner: Option<Entity>,
+    pub damage: u32,
+    // The strength of the knockback in the direction of flight
+    pub knockback: f32,
+    // The time left in seconds
+    pub lifetime: f32,
+    // The number of targets the projectile passes through before it is gone
+    pub pierce: u32,
+    // The collider tags of the targets it hits
+    pub hits: Vec<String>,
+    pub ground: GroundContact,
+    // The velocity before the collisions of this frame
+    pub velocity: Vec2,
+    // The entities the projectile already hit, they aren't hit again
+    pub hit: Vec<Entity>,
+}
+
+/// An event that fires a projectile
+#[derive(Debug, Default, Clone)]
+pub struct SpawnProjectileEvent {
+    pub texture: Handle<Image>,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub gravity_scale: f32,
+    pub half_extents: Vec2,
+    pub owner: Option<Entity>,
+    pub damage: u32,
+    pub knockback: f32,
+    pub lifetime: f32,
+    pub pierce: u32,
+    pub hits: Vec<String>,
+    pub ground: GroundContact,
+}
+
+/// The projectiles that are done and wait to be fired again
+#[derive(Debug, Clone)]
+pub struct ProjectilePool {
+    pub parked: Vec<Entity>,
+    // The most projectiles that are kept around, the rest are despawned
+    pub capacity: usize,
+}
+
+impl Default for ProjectilePool {
+    fn default() -> Self {
+        Self {
+            parked: vec![],
+            capacity: 64,
+        }
+    }
+}
+
+impl ProjectilePool {
+    /// Hide a projectile and take it out of the physics until it is fired again
+    fn park(&mut self, commands: &mut Commands, entity: Entity) {
+        if self.parked.contains(&entity) {
+            return;
+        }
+        if self.parked.len() >= self.capacity {
+            commands.entity(entity).despawn();
+            return;
+        }
+        commands
+            .entity(entity)
+            .remove::<Projectile>()
+            .remove::<Collider>()
+            .insert(PhysicsBody::Static)
+            .insert(Velocity(Vec2::ZERO))
+            .insert(Visibility { is_visible: false });
+        self.parked.push(entity);
+    }
+}
+
+/// The plugin that handles the projectiles
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProjectilePool>();
+        app.add_event::<SpawnProjectileEvent>();
+        // Fire after the projectiles are parked, so a reused one isn't parked again, and after the
+        // pool is cleared, so a projectile of a destroyed level isn't reused
+        app.add_system(
+            spawn_projectiles
+                .label("spawn_projectiles")
+                .after("update_projectiles")
+                .after("clear_projectile_pool"),
+        );
+        app.add_system(
+            clear_projectile_pool
+                .label("clear_projectile_pool")
+                .after("destroy_levels"),
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(
+                    remember_projectile_velocity
+                        .after("apply_gravity")
+                        .before("handle_collision"),
+                )
+                .with_system(
+                    update_projectiles
+                        .label("update_projectiles")
+                        .after("handle_collision")
+                        .before("apply_velocity"),
+                ),
+        );
+    }
+}
+
+/// Fire the projectiles, reusing the parked ones before spawning new ones
+fn spawn_projectiles(
+    mut commands: Commands,
+    mut pool: ResMut<ProjectilePool>,
+    mut spawn_events: EventReader<SpawnProjectileEvent>,
+) {
+    for event in spawn_events.iter() {
+        let mut entity = match pool.parked.pop() {
+            Some(entity) => commands.entity(entity),
+            None => commands.spawn(),
+        };
+        entity
+            .insert_bundle(SpriteBundle {
+                texture: event.texture.clone(),
+                ..Default::default()
+            })
+            .insert_bundle(PhysicsBodyBundle {
+                velocity: event.velocity.into(),
+                gravity_scale: GravityScale(event.gravity_scale),
+                ..Default::default()
+            })
+            .insert(Transform::from_xyz(event.position.x, event.position.y, 0.0))
+            .insert(Collider {
+                tags: vec!["projectile".into()],
+                colliding_tags: vec!["ground".into()],
+                half_extents: event.half_extents,
+            })
+            .insert(CollidingEntities::default())
+            .insert(Projectile {
+                owner: event.owner,
+                damage: event.damage,
+                knockback: event.knockback,
+                lifetime: event.lifetime,
+                pierce: event.pierce,
+                hits: event.hits.clone(),
+                ground: event.ground,
+                velocity: event.velocity,
+                hit: vec![],
+            })
+            .insert(LevelObject);
+    }
+}
+
+/// Forget the parked projectiles when the level they belong to is destroyed
+fn clear_projectile_pool(
+    mut pool: ResMut<ProjectilePool>,
+    mut destroy_level_events: EventReader<DestroyLevelsEvent>,
+) {
+    if destroy_level_events.iter().count() > 0 {
+        pool.parked.clear();
+    }
+}
+
+/// Keep the velocity from before the collisions, so the projectiles can bounce off the ground
+fn remember_projectile_velocity(mut projectiles: Query<(&mut Projectile, &Velocity)>) {
+    projectiles.for_each_mut(|(mut projectile, velocity)| {
+        projectile.velocity = velocity.0;
+    });
+}
+
+/// Damage the targets the projectiles touch, bounce them off the ground and park the ones that
+/// are done
+fn update_projectiles(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    active_view: Res<ActiveView>,
+    mut pool: ResMut<ProjectilePool>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut Velocity, &CollidingEntities)>,
+    colliders: Query<(&Collider, Option<&Health>, Option<&Dimensional>), Without<Projectile>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    projectiles.for_each_mut(|(entity, mut projectile, mut velocity, colliding_entities)| {
+        projectile.lifetime -= time.delta_seconds();
+        if projectile.lifetime <= 0.0 {
+            pool.park(&mut commands, entity);
+            return;
+        }
+
+        let mut done = false;
+        let mut on_ground = false;
+        for other in colliding_entities.iter() {
+            if projectile.owner == Some(*other) || projectile.hit.contains(other) {
+                continue;
+            }
+            let (collider, health, dimensional) = match colliders.get(*other) {
+                Ok(collider) => collider,
+                Err(_) => continue,
+            };
+            // Only the colliders in the active view are touched
+            if !dimensional.map_or(true, |dimensional| dimensional.exists_in(active_view.view)) {
+                continue;
+            }
+            if collider.tags.iter().any(|tag| tag == "ground") {
+                on_ground = true;
+                continue;
+            }
+            // Only the targets with a matching tag are hit
+            if health.is_none() || !collider.tags.iter().any(|tag| projectile.hits.contains(tag)) {
+                continue;
+            }
+
+            // Knock the target in the direction of flight, and a little upwards
+            let direction = Vec2::new(projectile.velocity.x.signum(), 1.0).normalize();
+            damage_events.send(DamageEvent {
+                target: *other,
+                source: projectile.owner,
+                amount: projectile.damage,
+                knockback: direction * projectile.knockback,
+            });
+            projectile.hit.push(*other);
+
+            if projectile.pierce == 0 {
+                done = true;
+                break;
+            }
+            projectile.pierce -= 1;
+        }
+
+        if on_ground && !done {
+            match projectile.ground {
+                GroundContact::Despawn => done = true,
+                GroundContact::Bounce { restitution } => {
+                    // The physics stopped the axes that hit the ground, send them back
+                    if velocity.x == 0.0 {
+                        velocity.x = -projectile.velocity.x * restitution;
+                    }
+                    if velocity.y == 0.0 {
+                        velocity.y = -projectile.velocity.y * restitution;
+                    }
+                }
+            }
+        }
+        if done {
+            pool.park(&mut commands, entity);
+        }
+    });
+}
@@ -0,0 +1,595 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    levels::SpawnLevelEvent,
+    player::{
+        component::{ControllerState, PlayerInputState, PlayerSlot, PlayerState},
+        slots::{PlayerSlots, MAX_PLAYERS},
+    },
+    rng::GameRng,
+    save::SaveData,
+    simulation::SimulationTime,
+    state::GameState,
+};
+
+/// The bytes every replay file starts with
+const REPLAY_MAGIC: &[u8; 4] = b"IPRP";
+/// The version of the replay format
+const REPLAY_VERSION: u8 = 2;
+
+/// The input of all the players during a single tick
+#[derive(Debug, Default, Clone)]
+pub struct ReplayTick {
+    // The time step of the tick
+    pub delta: f32,
+    // The input of each player by slot
+    pub inputs: Vec<(usize, PlayerInputState)>,
+}
+
+/// The actions taken in a menu during a single frame
+/// Menus don't advance the ticks, so a frame belongs to the tick the menu was opened at
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MenuFrame {
+    pub tick: u64,
+    // The actions by their index in the list of actions of the menu
+    pub actions: Vec<u8>,
+}
+
+/// The index of the action that closes a menu, in the list of actions of every menu
+pub const MENU_CLOSE: u8 = 0;
+
+/// A recorded run that can be played back
+#[derive(Debug, Default, Clone)]
+pub struct Replay {
+    // The seed of the random number generator
+    pub seed: u64,
+    // The level the run was played in
+    pub level: PathBuf,
+    // The slots that were joined
+    pub slots: Vec<usize>,
+    // The saved progress the run started from
+    pub save: SaveData,
+    // The recorded ticks
+    pub ticks: Vec<ReplayTick>,
+    // The frames that were spent in a menu and took actions
+    pub menu_frames: Vec<MenuFrame>,
+}
+
+impl Replay {
+    /// Encode the replay into its compact binary form
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(REPLAY_MAGIC);
+        bytes.push(REPLAY_VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+
+        let level = self.level.to_string_lossy();
+        bytes.extend_from_slice(&(level.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(level.as_bytes());
+
+        bytes.push(self.slots.len() as u8);
+        bytes.extend(self.slots.iter().map(|slot| *slot as u8));
+
+        // The save data is small and changes shape over time, so it is kept as text
+        let save = ron::to_string(&self.save).unwrap_or_default();
+        bytes.extend_from_slice(&(save.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(save.as_bytes());
+
+        bytes.extend_from_slice(&(self.ticks.len() as u32).to_le_bytes());
+        for tick in self.ticks.iter() {
+            bytes.extend_from_slice(&tick.delta.to_le_bytes());
+            // The players are stored in the same order as the slots
+            for slot in self.slots.iter() {
+                let input = tick
+                    .inputs
+                    .iter()
+                    .find(|(input_slot, _)| input_slot == slot)
+                    .map(|(_, input)| input.clone())
+                    .unwrap_or_default();
+                bytes.extend_from_slice(&encode_input_flags(&input).to_le_bytes());
+                bytes.extend_from_slice(&input.horizontal.to_le_bytes());
+            }
+        }
+
+        bytes.extend_from_slice(&(self.menu_frames.len() as u32).to_le_bytes());
+        for frame in self.menu_frames.iter() {
+            bytes.extend_from_slice(&frame.tick.to_le_bytes());
+            bytes.push(frame.actions.len() as u8);
+            bytes.extend_from_slice(&frame.actions);
+        }
+        bytes
+    }
+
+    /// Decode a replay from its compact binary form
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = ByteReader { bytes, position: 0 };
+
+        if reader.take(4)? != REPLAY_MAGIC {
+            return Err(invalid_data("Not a replay file"));
+        }
+        let version = reader.take(1)?[0];
+        if version != REPLAY_VERSION {
+            return Err(invalid_data(&format!("Unsupported replay version: {}", version)));
+        }
+        let seed = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+
+        let level_len = reader.read_u32()? as usize;
+        let level = String::from_utf8(reader.take(level_len)?.to_vec())
+            .map_err(|_| invalid_data("The level path is not valid utf-8"))?;
+
+        let slot_count = reader.take(1)?[0] as usize;
+        let slots: Vec<usize> = reader
+            .take(slot_count)?
+            .iter()
+            .map(|slot| *slot as usize)
+            .collect();
+        if slots.iter().any(|slot| *slot >= MAX_PLAYERS) {
+            return Err(invalid_data("The replay has an invalid player slot"));
+        }
+
+        let save_len = reader.read_u32()? as usize;
+        let save = std::str::from_utf8(reader.take(save_len)?)
+            .map_err(|_| invalid_data("The save data is not valid utf-8"))?;
+        let save = if save.is_empty() {
+            SaveData::default()
+        } else {
+            ron::from_str(save).map_err(|_| invalid_data("The save data is malformed"))?
+        };
+
+        let tick_count = reader.read_u32()? as usize;
+        let mut ticks = Vec::with_capacity(tick_count);
+        for _ in 0..tick_count {
+            let delta = reader.read_f32()?;
+            let mut inputs = Vec::with_capacity(slots.len());
+            for slot in slots.iter() {
+                let flags = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+                let mut input = decode_input_flags(flags);
+                input.horizontal = reader.read_f32()?;
+                inputs.push((*slot, input));
+            }
+            ticks.push(ReplayTick { delta, inputs });
+        }
+
+        let frame_count = reader.read_u32()? as usize;
+        let mut menu_frames = Vec::with_capacity(frame_count);
+        for _ in 0..frame_count {
+            let tick = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+            let action_count = reader.take(1)?[0] as usize;
+            let actions = reader.take(action_count)?.to_vec();
+            menu_frames.push(MenuFrame { tick, actions });
+        }
+
+        Ok(Self {
+            seed,
+            level: level.into(),
+            slots,
+            save,
+            ticks,
+            menu_frames,
+        })
+    }
+
+    /// Save the replay to a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Load a replay from a file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// Packs the buttons of the input into bit flags
+fn encode_input_flags(input: &PlayerInputState) -> u16 {
+    (input.is_jumping as u16)
+        | ((input.is_sprinting as u16) << 1)
+        | ((input.is_crouching as u16) << 2)
+        | ((input.is_dashing as u16) << 3)
+        | ((input.is_flipping as u16) << 4)
+        | ((input.is_using_item as u16) << 5)
+        | ((input.is_cycling_item as u16) << 6)
+        | ((input.is_throwing as u16) << 7)
+        | ((input.is_interacting as u16) << 8)
+        | ((input.is_attacking as u16) << 9)
+        | ((input.is_opening_menu as u16) << 10)
+}
+
+/// Unpacks the buttons of the input from bit flags
+fn decode_input_flags(flags: u16) -> PlayerInputState {
+    PlayerInputState {
+        is_jumping: flags & 1 != 0,
+        is_sprinting: flags & (1 << 1) != 0,
+        is_crouching: flags & (1 << 2) != 0,
+        is_dashing: flags & (1 << 3) != 0,
+        is_flipping: flags & (1 << 4) != 0,
+        is_using_item: flags & (1 << 5) != 0,
+        is_cycling_item: flags & (1 << 6) != 0,
+        is_throwing: flags & (1 << 7) != 0,
+        is_interacting: flags & (1 << 8) != 0,
+        is_attacking: flags & (1 << 9) != 0,
+        is_opening_menu: flags & (1 << 10) != 0,
+        ..Default::default()
+    }
+}
+
+/// Creates an error for a malformed replay
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Reads the fields of a replay in order
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    /// Take the next bytes
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.position + len;
+        if end > self.bytes.len() {
+            return Err(invalid_data("The replay ended unexpectedly"));
+        }
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Take the next u32
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Take the next f32
+    fn read_f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Whether the game is recording or playing back a run
+#[derive(Debug, Clone)]
+pub enum ReplayMode {
+    Off,
+    Recording { path: PathBuf, replay: Replay },
+    Playing {
+        replay: Replay,
+        started: bool,
+        // The index of the next menu frame to play back
+        next_menu_frame: usize,
+    },
+}
+
+impl Default for ReplayMode {
+    fn default() -> Self {
+        Self::Off
+    }
+}
+
+impl ReplayMode {
+    /// Check if a run is recorded or played back
+    pub fn is_active(&self) -> bool {
+        !matches!(self, Self::Off)
+    }
+
+    /// Get the recorded input of a player slot for a tick, while a replay is played back
+    /// The input of the players is read from their devices when this is `None`
+    pub fn playback_input(&self, tick: u64, slot: usize) -> Option<PlayerInputState> {
+        match self {
+            Self::Playing {
+                replay,
+                started: true,
+                ..
+            } => replay.ticks.get(tick as usize).map(|tick| {
+                tick.inputs
+                    .iter()
+                    .find(|(input_slot, _)| *input_slot == slot)
+                    .map(|(_, input)| input.clone())
+                    .unwrap_or_default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Record the actions a menu took this frame, while a run is recorded
+    pub fn record_menu_actions(&mut self, tick: u64, actions: Vec<u8>) {
+        if let Self::Recording { replay, .. } = self {
+            if !actions.is_empty() {
+                replay.menu_frames.push(MenuFrame { tick, actions });
+            }
+        }
+    }
+
+    /// Take the actions of the next recorded menu frame, while a replay is played back
+    /// A menu without recorded frames left at the tick is closed, so the replay carries on
+    /// The actions are read from the devices when this is `None`
+    pub fn playback_menu_actions(&mut self, tick: u64) -> Option<Vec<u8>> {
+        if let Self::Playing {
+            replay,
+            started: true,
+            next_menu_frame,
+        } = self
+        {
+            match replay.menu_frames.get(*next_menu_frame) {
+                Some(frame) if frame.tick == tick => {
+                    *next_menu_frame += 1;
+                    return Some(frame.actions.clone());
+                }
+                _ if (tick as usize) < replay.ticks.len() => return Some(vec![MENU_CLOSE]),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// The plugin that records and plays back the player input
+/// Start the game with `--record <file>` to record a run, or with `--replay <file>` to play it back
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRng>();
+        app.insert_resource(replay_mode_from_args());
+        app.add_system(start_level.before("spawn_level"));
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            playback_time.after("update_simulation_time"),
+        );
+        app.add_system_set(SystemSet::on_update(GameState::MainMenu).with_system(start_playback));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(record_inputs.after("update_input").before("advance_tick")),
+        );
+        app.add_system_set(SystemSet::on_exit(GameState::Gameplay).with_system(save_recording));
+        // Closing the window doesn't leave the gameplay, so the recording is saved on exit too
+        app.add_system_to_stage(CoreStage::Last, save_recording_on_exit);
+    }
+}
+
+/// Read the replay mode from the command line
+fn replay_mode_from_args() -> ReplayMode {
+    let args: Vec<String> = std::env::args().collect();
+    let value_of = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|index| args.get(index + 1))
+            .map(PathBuf::from)
+    };
+
+    if let Some(path) = value_of("--replay") {
+        match Replay::load(&path) {
+            Ok(replay) => {
+                info!("Playing back replay: {}", path.display());
+                return ReplayMode::Playing {
+                    replay,
+                    started: false,
+                    next_menu_frame: 0,
+                };
+            }
+            Err(err) => error!("Failed to load replay {}: {}", path.display(), err),
+        }
+    }
+    if let Some(path) = value_of("--record") {
+        info!("Recording replay: {}", path.display());
+        return ReplayMode::Recording {
+            path,
+            replay: Replay::default(),
+        };
+    }
+    ReplayMode::Off
+}
+
+/// Seed the random number generator and restart the recording when a level is spawned
+/// A replay starts from the progress that was saved when it was recorded
+fn start_level(
+    mut mode: ResMut<ReplayMode>,
+    mut rng: ResMut<GameRng>,
+    mut save_data: ResMut<SaveData>,
+    mut simulation_time: ResMut<SimulationTime>,
+    slots: Res<PlayerSlots>,
+    mut spawn_level_events: EventReader<SpawnLevelEvent>,
+) {
+    for SpawnLevelEvent(level) in spawn_level_events.iter() {
+        simulation_time.tick = 0;
+        match &mut *mode {
+            ReplayMode::Playing {
+                replay,
+                next_menu_frame,
+                ..
+            } => {
+                rng.reseed(replay.seed);
+                *next_menu_frame = 0;
+                *save_data = replay.save.clone();
+            }
+            ReplayMode::Recording { replay, .. } => {
+                let seed = rng.next_u64();
+                rng.reseed(seed);
+                *replay = Replay {
+                    seed,
+                    level: level.clone(),
+                    slots: slots.joined().map(|(slot, _)| slot).collect(),
+                    save: save_data.clone(),
+                    ticks: vec![],
+                    menu_frames: vec![],
+                };
+            }
+            ReplayMode::Off => {
+                let seed = rng.next_u64();
+                rng.reseed(seed);
+            }
+        }
+    }
+}
+
+/// Start the replayed run in place of the main menu
+fn start_playback(
+    mut mode: ResMut<ReplayMode>,
+    mut slots: ResMut<PlayerSlots>,
+    mut gamestate: ResMut<State<GameState>>,
+    mut spawn_level_events: EventWriter<SpawnLevelEvent>,
+) {
+    if let ReplayMode::Playing { replay, started, .. } = &mut *mode {
+        if *started {
+            return;
+        }
+        *started = true;
+
+        // The recorded input replaces the devices, so the slots only need to be claimed
+        slots.clear();
+        for slot in replay.slots.iter() {
+            slots.slots[*slot] = Some(ControllerState::Keyboard);
+        }
+
+        if *gamestate.current() != GameState::Gameplay {
+            gamestate.set(GameState::Gameplay).unwrap();
+        }
+        spawn_level_events.send(SpawnLevelEvent(replay.level.clone()));
+    }
+}
+
+/// Use the recorded time step while playing back
+fn playback_time(mode: Res<ReplayMode>, mut simulation_time: ResMut<SimulationTime>) {
+    if let ReplayMode::Playing {
+        replay,
+        started: true,
+        ..
+    } = &*mode
+    {
+        match replay.ticks.get(simulation_time.tick as usize) {
+            Some(tick) => {
+                simulation_time.delta = tick.delta;
+                simulation_time.overridden = true;
+            }
+            None => {
+                if simulation_time.overridden {
+                    info!("The replay is finished");
+                }
+                simulation_time.overridden = false;
+            }
+        }
+    }
+}
+
+/// Record the input of the players for the current tick
+fn record_inputs(
+    mut mode: ResMut<ReplayMode>,
+    simulation_time: Res<SimulationTime>,
+    players: Query<(&PlayerSlot, &PlayerState)>,
+) {
+    if let ReplayMode::Recording { replay, .. } = &mut *mode {
+        let mut inputs: Vec<(usize, PlayerInputState)> = players
+            .iter()
+            .map(|(slot, state)| (slot.0, state.input.clone()))
+            .collect();
+        inputs.sort_by_key(|(slot, _)| *slot);
+
+        replay.ticks.push(ReplayTick {
+            delta: simulation_time.delta,
+            inputs,
+        });
+    }
+}
+
+/// Write the recording to its file when gameplay is left
+fn save_recording(mode: Res<ReplayMode>) {
+    write_recording(&mode);
+}
+
+/// Write the recording to its file when the game is closed
+fn save_recording_on_exit(mode: Res<ReplayMode>, mut exit_events: EventReader<AppExit>) {
+    if exit_events.iter().count() > 0 {
+        write_recording(&mode);
+    }
+}
+
+/// Write the recording to its file, if a run is recorded
+fn write_recording(mode: &ReplayMode) {
+    if let ReplayMode::Recording { path, replay } = mode {
+        match replay.save(path) {
+            Ok(()) => info!("Saved replay to {}", path.display()),
+            Err(err) => error!("Failed to save replay {}: {}", path.display(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        let mut save = SaveData::default();
+        save.world_flags.insert("lock:gate".into());
+        let input = |is_jumping, is_opening_menu, horizontal| PlayerInputState {
+            is_jumping,
+            is_opening_menu,
+            horizontal,
+            ..Default::default()
+        };
+        Replay {
+            seed: 42,
+            level: "levels/test.level".into(),
+            slots: vec![0, 2],
+            save,
+            ticks: vec![
+                ReplayTick {
+                    delta: 1.0 / 60.0,
+                    inputs: vec![(0, input(true, false, -1.0)), (2, input(false, false, 0.5))],
+                },
+                ReplayTick {
+                    delta: 1.0 / 30.0,
+                    inputs: vec![(0, input(false, true, 0.0)), (2, input(true, false, 1.0))],
+                },
+            ],
+            menu_frames: vec![MenuFrame {
+                tick: 1,
+                actions: vec![3, MENU_CLOSE],
+            }],
+        }
+    }
+
+    #[test]
+    fn replay_survives_a_round_trip() {
+        let replay = replay();
+        let decoded = Replay::from_bytes(&replay.to_bytes()).unwrap();
+
+        assert_eq!(decoded.seed, replay.seed);
+        assert_eq!(decoded.level, replay.level);
+        assert_eq!(decoded.slots, replay.slots);
+        assert_eq!(decoded.save.world_flags, replay.save.world_flags);
+        assert_eq!(decoded.menu_frames, replay.menu_frames);
+        assert_eq!(decoded.ticks.len(), replay.ticks.len());
+        for (decoded_tick, tick) in decoded.ticks.iter().zip(replay.ticks.iter()) {
+            assert_eq!(decoded_tick.delta, tick.delta);
+            for (decoded_input, input) in decoded_tick.inputs.iter().zip(tick.inputs.iter()) {
+                assert_eq!(decoded_input.0, input.0);
+                assert_eq!(encode_input_flags(&decoded_input.1), encode_input_flags(&input.1));
+                assert_eq!(decoded_input.1.horizontal, input.1.horizontal);
+            }
+        }
+    }
+
+    #[test]
+    fn replay_rejects_other_versions_and_broken_files() {
+        let mut bytes = replay().to_bytes();
+
+        let mut other_version = bytes.clone();
+        other_version[REPLAY_MAGIC.len()] = REPLAY_VERSION + 1;
+        let err = Replay::from_bytes(&other_version).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("version"));
+
+        let mut other_magic = bytes.clone();
+        other_magic[0] = b'X';
+        assert!(Replay::from_bytes(&other_magic).is_err());
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(Replay::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::{collections::BTreeSet, error::Error, fs, path::Path};
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{
+        abilities::Abilities,
+        component::{Player, PlayerSlot},
+    },
+    replay::ReplayMode,
+    state::GameState,
+};
+
+/// The file the game is saved to
+pub const SAVE_PATH: &str = "save.ron";
+
+/// The saved progress of a player
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PlayerSaveData {
+    pub slot: usize,
+    pub abilities: Abilities,
+}
+
+/// The saved progress of the game
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SaveData {
+    pub players: Vec<PlayerSaveData>,
+    // The flags that remember changes to the world, like opened locks
+    #[serde(default)]
+    pub world_flags: BTreeSet<String>,
+}
+
+impl SaveData {
+    /// Get the saved progress of the player in a slot
+    pub fn player(&self, slot: usize) -> Option<&PlayerSaveData> {
+        self.players.iter().find(|player| player.slot == slot)
+    }
+
+    /// Get the saved progress of the player in a slot, creating it if it doesn't exist yet
+    pub fn player_mut(&mut self, slot: usize) -> &mut PlayerSaveData {
+        if let Some(index) = self.players.iter().position(|player| player.slot == slot) {
+            return &mut self.players[index];
+        }
+        self.players.push(PlayerSaveData {
+            slot,
+            ..Default::default()
+        });
+        self.players.last_mut().unwrap()
+    }
+
+    /// Check if a world flag is set
+    pub fn flag(&self, flag: &str) -> bool {
+        self.world_flags.contains(flag)
+    }
+
+    /// Set a world flag, it is written to the file with the next save
+    pub fn set_flag(&mut self, flag: &str) {
+        self.world_flags.insert(flag.into());
+    }
+
+    /// Write the save data to a file
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let data = ron::ser::to_string_pretty(self, PrettyConfig::default())?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Read the save data from a file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let data = fs::read_to_string(path)?;
+        Ok(ron::from_str(&data)?)
+    }
+}
+
+/// An event that saves the game
+pub struct SaveEvent;
+
+/// An event that loads the last save
+pub struct LoadEvent;
+
+/// The plugin that saves and loads the game
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        // Continue from the last save
+        let save_data = if Path::new(SAVE_PATH).exists() {
+            SaveData::load(Path::new(SAVE_PATH)).unwrap_or_else(|err| {
+                error!("Failed to load {}: {}", SAVE_PATH, err);
+                SaveData::default()
+            })
+        } else {
+            SaveData::default()
+        };
+
+        app.insert_resource(save_data);
+        app.add_event::<SaveEvent>();
+        app.add_event::<LoadEvent>();
+        app.add_system(apply_save_to_spawned_players);
+        app.add_system(save_game.label("save_game"));
+        app.add_system(load_game.after("save_game"));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay).with_system(save_hotkeys),
+        );
+    }
+}
+
+/// Save on F5 and load on F9
+fn save_hotkeys(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut save_events: EventWriter<SaveEvent>,
+    mut load_events: EventWriter<LoadEvent>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        save_events.send(SaveEvent);
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        load_events.send(LoadEvent);
+    }
+}
+
+/// Give the freshly spawned players their saved progress
+fn apply_save_to_spawned_players(
+    save_data: Res<SaveData>,
+    mut players: Query<(&PlayerSlot, &mut Abilities), Added<Player>>,
+) {
+    players.for_each_mut(|(slot, mut abilities)| {
+        if let Some(saved) = save_data.player(slot.0) {
+            *abilities = saved.abilities.clone();
+        }
+    });
+}
+
+/// Store the progress of the players and write it to the save file
+fn save_game(
+    replay_mode: Res<ReplayMode>,
+    mut save_data: ResMut<SaveData>,
+    mut save_events: EventReader<SaveEvent>,
+    players: Query<(&PlayerSlot, &Abilities), With<Player>>,
+) {
+    if save_events.iter().count() == 0 {
+        return;
+    }
+    // A replay starts from the save it was recorded with, so the save is left alone during one
+    if replay_mode.is_active() {
+        info!("Not saving while a replay is recorded or played back");
+        return;
+    }
+
+    players.for_each(|(slot, abilities)| {
+        save_data.player_mut(slot.0).abilities = abilities.clone();
+    });
+
+    match save_data.save(Path::new(SAVE_PATH)) {
+        Ok(()) => info!("Saved the game to {}", SAVE_PATH),
+        Err(err) => error!("Failed to save the game to {}: {}", SAVE_PATH, err),
+    }
+}
+
+/// Read the save file and restore the progress of the players
+fn load_game(
+    replay_mode: Res<ReplayMode>,
+    mut save_data: ResMut<SaveData>,
+    mut load_events: EventReader<LoadEvent>,
+    mut players: Query<(&PlayerSlot, &mut Abilities), With<Player>>,
+) {
+    if load_events.iter().count() == 0 {
+        return;
+    }
+    if replay_mode.is_active() {
+        info!("Not loading while a replay is recorded or played back");
+        return;
+    }
+
+    match SaveData::load(Path::new(SAVE_PATH)) {
+        Ok(loaded) => *save_data = loaded,
+        Err(err) => {
+            error!("Failed to load {}: {}", SAVE_PATH, err);
+            return;
+        }
+    }
+
+    players.for_each_mut(|(slot, mut abilities)| {
+        if let Some(saved) = save_data.player(slot.0) {
+            *abilities = saved.abilities.clone();
+        }
+    });
+    info!("Loaded the game from {}", SAVE_PATH);
+}
@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+
+use crate::state::GameState;
+
+/// The time step the gameplay systems simulate with
+/// Follows the frame time, unless a replay dictates the time step
+#[derive(Debug, Default, Clone)]
+pub struct SimulationTime {
+    // The time step of the current tick in seconds
+    pub delta: f32,
+    // The amount of gameplay ticks that were simulated
+    pub tick: u64,
+    // Whether the time step is set by something other than the frame time
+    pub overridden: bool,
+}
+
+impl SimulationTime {
+    /// The time step of the current tick in seconds
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+}
+
+/// The plugin that provides the simulation time
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationTime>();
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            update_simulation_time.label("update_simulation_time"),
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(advance_tick.label("advance_tick").after("apply_friction")),
+        );
+    }
+}
+
+/// Follow the frame time
+fn update_simulation_time(time: Res<Time>, mut simulation_time: ResMut<SimulationTime>) {
+    if !simulation_time.overridden {
+        simulation_time.delta = time.delta_seconds();
+    }
+}
+
+/// Count the simulated gameplay ticks
+fn advance_tick(mut simulation_time: ResMut<SimulationTime>) {
+    simulation_time.tick += 1;
+}
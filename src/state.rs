@@ -5,9 +5,12 @@ use bevy::prelude::*;
 pub enum GameState {
     Startup,
     MainMenu,
+    Lobby,
     Gameplay,
     Paused,
     Menu,
+    Shop,
+    Container,
     Cutscene,
     GameOver,
 }